@@ -0,0 +1,24 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const ROM: &str = "example_rom/garden.bin";
+
+// Measures how many frames per second the core emulates, without any rendering on top.
+fn frames(c: &mut Criterion) {
+    let mut emulator_core = atari2600_lib::init_emulator(ROM).unwrap();
+    // Get past the boot sequence so the benchmark measures regular gameplay frames
+    for _ in 0..60 {
+        emulator_core.run();
+    }
+
+    let mut group = c.benchmark_group("frames");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("run", |b| b.iter(|| emulator_core.run()));
+
+    // The same, taking a save state after every frame for rewinding
+    emulator_core.set_rewind_length(600);
+    group.bench_function("run_with_rewind", |b| b.iter(|| emulator_core.run()));
+    group.finish();
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
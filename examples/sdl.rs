@@ -12,8 +12,6 @@ use std::error::Error;
 use std::thread;
 use std::time::{Duration, Instant};
 
-const ATARI_FPS: f64 = 60.0;
-const FRAME_DURATION: Duration = Duration::from_millis(((1.0 / ATARI_FPS) * 1000.0) as u64);
 const HORIZONTAL_SCALING_FACTOR: usize = 4;
 const VERTICAL_SCALING_FACTOR: usize = 2;
 
@@ -50,7 +48,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         handle_events(&mut emulator_core, &mut event_pump);
 
-        if let Some(delay) = FRAME_DURATION.checked_sub(fps_start.elapsed()) {
+        let frame_duration = Duration::from_secs_f64(1.0 / emulator_core.target_fps());
+        if let Some(delay) = frame_duration.checked_sub(fps_start.elapsed()) {
             thread::sleep(delay);
         }
 
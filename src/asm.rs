@@ -0,0 +1,189 @@
+// Assembles test programs, so that tests can spell out instructions instead of opcode bytes:
+//
+//     let mut asm = Asm::new(0xf000);
+//     asm.ldx_imm(5);
+//     let top = asm.here();
+//     asm.sta_zp(0x02).dex().bne(top);
+//     let rom = asm.rom();
+//
+// Only the instructions tests have needed so far are here, add more as they come up.
+use alloc::{vec, vec::Vec};
+
+const CARTRIDGE_SIZE: usize = 4096;
+const RESET_VECTOR: usize = 0xffc;
+
+pub(crate) struct Asm {
+    origin: u16,
+    bytes: Vec<u8>,
+}
+
+// Not every test uses every instruction
+#[allow(dead_code)]
+impl Asm {
+    pub fn new(origin: u16) -> Self {
+        Self {
+            origin,
+            bytes: Vec::new(),
+        }
+    }
+
+    // Address of the next instruction, to branch or jump back to
+    pub fn here(&self) -> u16 {
+        self.origin + self.bytes.len() as u16
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // A 4K cartridge image with the program at its origin, padded with NOPs, and the reset vector
+    // pointing at the start of the program
+    pub fn rom(&self) -> Vec<u8> {
+        assert!(self.origin & 0x1000 != 0, "origin isn't in cartridge space");
+        let start = self.origin as usize & 0xfff;
+        let mut rom = vec![0xea; CARTRIDGE_SIZE];
+        rom[start..start + self.bytes.len()].copy_from_slice(&self.bytes);
+        rom[RESET_VECTOR..RESET_VECTOR + 2].copy_from_slice(&self.origin.to_le_bytes());
+        rom
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> &mut Self {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    fn emit_abs(&mut self, opcode: u8, addr: u16) -> &mut Self {
+        let [lo, hi] = addr.to_le_bytes();
+        self.emit(&[opcode, lo, hi])
+    }
+
+    // A branch to `target`, which has to be within reach
+    fn emit_branch(&mut self, opcode: u8, target: u16) -> &mut Self {
+        let offset = target.wrapping_sub(self.here() + 2) as i16;
+        let offset = i8::try_from(offset).expect("branch target out of range");
+        self.emit(&[opcode, offset as u8])
+    }
+
+    pub fn lda_imm(&mut self, val: u8) -> &mut Self {
+        self.emit(&[0xa9, val])
+    }
+
+    pub fn lda_zp(&mut self, addr: u8) -> &mut Self {
+        self.emit(&[0xa5, addr])
+    }
+
+    pub fn lda_abs(&mut self, addr: u16) -> &mut Self {
+        self.emit_abs(0xad, addr)
+    }
+
+    pub fn ldx_imm(&mut self, val: u8) -> &mut Self {
+        self.emit(&[0xa2, val])
+    }
+
+    pub fn ldy_imm(&mut self, val: u8) -> &mut Self {
+        self.emit(&[0xa0, val])
+    }
+
+    pub fn sta_zp(&mut self, addr: u8) -> &mut Self {
+        self.emit(&[0x85, addr])
+    }
+
+    pub fn sta_abs(&mut self, addr: u16) -> &mut Self {
+        self.emit_abs(0x8d, addr)
+    }
+
+    pub fn stx_zp(&mut self, addr: u8) -> &mut Self {
+        self.emit(&[0x86, addr])
+    }
+
+    pub fn sty_zp(&mut self, addr: u8) -> &mut Self {
+        self.emit(&[0x84, addr])
+    }
+
+    pub fn asl(&mut self) -> &mut Self {
+        self.emit(&[0x0a])
+    }
+
+    pub fn inx(&mut self) -> &mut Self {
+        self.emit(&[0xe8])
+    }
+
+    pub fn dex(&mut self) -> &mut Self {
+        self.emit(&[0xca])
+    }
+
+    pub fn iny(&mut self) -> &mut Self {
+        self.emit(&[0xc8])
+    }
+
+    pub fn dey(&mut self) -> &mut Self {
+        self.emit(&[0x88])
+    }
+
+    pub fn cli(&mut self) -> &mut Self {
+        self.emit(&[0x58])
+    }
+
+    pub fn nop(&mut self) -> &mut Self {
+        self.emit(&[0xea])
+    }
+
+    pub fn jam(&mut self) -> &mut Self {
+        self.emit(&[0x02])
+    }
+
+    pub fn bne(&mut self, target: u16) -> &mut Self {
+        self.emit_branch(0xd0, target)
+    }
+
+    pub fn beq(&mut self, target: u16) -> &mut Self {
+        self.emit_branch(0xf0, target)
+    }
+
+    pub fn jmp(&mut self, addr: u16) -> &mut Self {
+        self.emit_abs(0x4c, addr)
+    }
+
+    // JMP to itself, to park the CPU at the end of a program
+    pub fn jmp_here(&mut self) -> &mut Self {
+        let here = self.here();
+        self.jmp(here)
+    }
+
+    pub fn jsr(&mut self, addr: u16) -> &mut Self {
+        self.emit_abs(0x20, addr)
+    }
+
+    pub fn rts(&mut self) -> &mut Self {
+        self.emit(&[0x60])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_program_with_branches() {
+        let mut asm = Asm::new(0xf000);
+        asm.ldx_imm(5);
+        let top = asm.here();
+        asm.sta_zp(0x02).dex().bne(top).jmp_here();
+        assert_eq!(
+            asm.bytes(),
+            [0xa2, 0x05, 0x85, 0x02, 0xca, 0xd0, 0xfb, 0x4c, 0x07, 0xf0]
+        );
+
+        let rom = asm.rom();
+        assert_eq!(rom.len(), CARTRIDGE_SIZE);
+        assert_eq!(&rom[..2], [0xa2, 0x05]);
+        assert_eq!(rom[0xffc..0xffe], [0x00, 0xf0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "branch target out of range")]
+    fn far_branch_is_rejected() {
+        let mut asm = Asm::new(0xf000);
+        asm.bne(0xf100);
+    }
+}
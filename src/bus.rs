@@ -1,43 +1,344 @@
-use crate::memory::{MemoryMirrors, Operation};
-use crate::{SharedRIOT, SharedTIA};
+use crate::controller::{Controller, Direction, GenesisButton, GenesisPad, Joystick};
+use crate::memory::{
+    MemoryMirrors, MemoryRegion, Operation, PiaAddress, TiaReadAddress, TiaWriteAddress,
+};
+use crate::{riot::RIOT, tia::TIA};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use core::{error::Error, fmt};
 use log::error;
-use std::fs::File;
-use std::io;
+#[cfg(feature = "std")]
+use std::{fs::File, io};
 
+// By default, only the first invalid access to each address is logged
+const DEFAULT_LOG_LIMIT: usize = 1;
+
+/// A register access that is valid, but probably a bug, as reported while
+/// `EmulatorCore::set_register_warnings` is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterWarningKind {
+    /// A read of an address only a TIA write register is named after, such as RESP0 or HMOVE. It
+    /// reads whichever collision or input register shares the address.
+    ReadOfWriteRegister(TiaWriteAddress),
+    /// A read of one of the RIOT's timer interval registers, which can only be written.
+    ReadOfTimerInterval(PiaAddress),
+    /// A write to port B or its direction register. The console wires port B to its switches, so
+    /// these are read only.
+    WriteToConsoleSwitches(PiaAddress),
+}
+
+/// A register warning, with where it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWarning {
+    /// Address of the instruction that made the access.
+    pub pc: u16,
+    /// Address that was accessed.
+    pub address: u16,
+    pub kind: RegisterWarningKind,
+}
+
+impl fmt::Display for RegisterWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${:04X}: ", self.pc)?;
+        match self.kind {
+            RegisterWarningKind::ReadOfWriteRegister(register) => write!(
+                f,
+                "read of write only register {} at ${:04X}",
+                register, self.address
+            ),
+            RegisterWarningKind::ReadOfTimerInterval(register) => write!(
+                f,
+                "read of write only timer register {} at ${:04X}",
+                register, self.address
+            ),
+            RegisterWarningKind::WriteToConsoleSwitches(register) => write!(
+                f,
+                "write to read only console switch register {} at ${:04X}",
+                register, self.address
+            ),
+        }
+    }
+}
+
+/// Counts invalid memory accesses, and limits how many of them get logged so that a misbehaving
+/// ROM doesn't flood the logs.
+#[derive(Clone)]
+pub(crate) struct Diagnostics {
+    invalid_accesses: usize,
+    per_address: BTreeMap<u16, usize>,
+    log_limit: usize,
+
+    // Register warnings not yet picked up by `take_register_warning`, with the address accessed
+    register_warnings: bool,
+    pending_warnings: VecDeque<(u16, RegisterWarningKind)>,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self {
+            invalid_accesses: 0,
+            per_address: BTreeMap::new(),
+            log_limit: DEFAULT_LOG_LIMIT,
+            register_warnings: false,
+            pending_warnings: VecDeque::new(),
+        }
+    }
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn invalid_accesses(&self) -> usize {
+        self.invalid_accesses
+    }
+
+    // Sets how many invalid accesses are logged per address. Accesses are still counted once the
+    // limit is reached.
+    pub fn set_log_limit(&mut self, limit: usize) {
+        self.log_limit = limit;
+    }
+
+    pub fn set_register_warnings(&mut self, enabled: bool) {
+        self.register_warnings = enabled;
+        self.pending_warnings.clear();
+    }
+
+    pub fn take_register_warning(&mut self) -> Option<(u16, RegisterWarningKind)> {
+        self.pending_warnings.pop_front()
+    }
+
+    fn register_warning(&mut self, address: u16, kind: RegisterWarningKind) {
+        self.pending_warnings.push_back((address, kind));
+    }
+
+    fn invalid_access(&mut self, address: u16, e: Box<dyn Error>) {
+        self.invalid_accesses += 1;
+
+        let count = self.per_address.entry(address).or_insert(0);
+        *count += 1;
+
+        if *count < self.log_limit {
+            error!("{}", e);
+        } else if *count == self.log_limit {
+            error!("{} (further errors for this address suppressed)", e);
+        }
+    }
+}
+
+#[allow(dead_code)]
 pub trait Bus {
+    // The address lines the CPU drives. The 6507 only has 13 of them connected, a bus standing in
+    // for a plain 6502 system can widen this to the full 16.
+    const ADDRESS_MASK: u16 = 0x1fff;
+
     fn read(&mut self, _address: u16) -> u8 {
         0
     }
     fn write(&mut self, _address: u16, _val: u8) {}
+    #[cfg(feature = "std")]
     fn save(&self, _output: &mut File) -> io::Result<()> {
         Ok(())
     }
+    #[cfg(feature = "std")]
     fn load(&mut self, _input: &mut File) -> io::Result<()> {
         Ok(())
     }
 }
 
+// The bus owns the chips hanging off it. Everything else reaches them through the CPU's bus, which
+// keeps the per-clock path free of shared ownership and runtime borrow checks.
+#[derive(Clone)]
 pub(crate) struct AtariBus {
     rom: Vec<u8>,
-    tia: SharedTIA,
-    riot: SharedRIOT,
+    tia: TIA,
+    riot: RIOT,
+    diagnostics: Diagnostics,
+    // Joysticks 0 and 1 are in the left and right ports, 2 and 3 are the second joysticks of
+    // QuadTaris plugged into them
+    joysticks: [Joystick; 4],
+    genesis_pads: [GenesisPad; 2],
+}
+
+impl AtariBus {
+    pub fn new(tia: TIA, riot: RIOT, diagnostics: Diagnostics, rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            tia,
+            riot,
+            diagnostics,
+            joysticks: [Joystick::default(); 4],
+            genesis_pads: [GenesisPad::default(); 2],
+        }
+    }
+
+    pub fn tia(&self) -> &TIA {
+        &self.tia
+    }
+
+    pub fn tia_mut(&mut self) -> &mut TIA {
+        &mut self.tia
+    }
+
+    pub fn riot(&self) -> &RIOT {
+        &self.riot
+    }
+
+    pub fn riot_mut(&mut self) -> &mut RIOT {
+        &mut self.riot
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn diagnostics_mut(&mut self) -> &mut Diagnostics {
+        &mut self.diagnostics
+    }
+}
+
+// Controllers wired to both chips
+impl AtariBus {
+    pub fn set_controller(&mut self, port: usize, controller: Controller) {
+        assert!(port < 2, "controller port {} out of range", port);
+        if self.riot.controller(port) == Controller::Genesis && controller != Controller::Genesis {
+            self.tia.set_paddle(2 * port + 1, None);
+        }
+        self.riot.set_controller(port, controller);
+        self.update_controllers();
+    }
+
+    pub fn set_joystick(&mut self, index: usize, direction: Direction, pressed: bool) {
+        assert!(index < 4, "joystick {} out of range", index);
+        let joystick = &mut self.joysticks[index];
+        if pressed {
+            joystick.directions |= direction.bit();
+        } else {
+            joystick.directions &= !direction.bit();
+        }
+        self.update_controllers();
+    }
+
+    pub fn set_joystick_fire(&mut self, index: usize, pressed: bool) {
+        assert!(index < 4, "joystick {} out of range", index);
+        self.joysticks[index].fire = pressed;
+        self.update_controllers();
+    }
+
+    pub fn set_genesis_button(&mut self, port: usize, button: GenesisButton, pressed: bool) {
+        assert!(port < 2, "Genesis pad port {} out of range", port);
+        match button {
+            GenesisButton::A => self.genesis_pads[port].a = pressed,
+            GenesisButton::B => self.joysticks[port].fire = pressed,
+            GenesisButton::C => self.genesis_pads[port].c = pressed,
+        }
+        self.update_controllers();
+    }
+
+    // Puts what the controllers currently show onto the chips' inputs. Called whenever an input
+    // changes, and whenever the game writes a register that controllers can switch on.
+    pub fn update_controllers(&mut self) {
+        self.update_joysticks();
+        self.update_genesis_pads();
+    }
+
+    // Puts the directions and fire button of the joystick each port shows onto SWCHA and INPT4/5.
+    // A QuadTari switches between its joysticks on the same VBLANK bit that grounds the paddles.
+    fn update_joysticks(&mut self) {
+        for port in 0..2 {
+            let second =
+                self.riot.controller(port) == Controller::QuadTari && !self.tia.paddles_dumped();
+            let joystick = self.joysticks[if second { port + 2 } else { port }];
+            self.riot.set_joystick(port, joystick.directions);
+            self.tia.set_fire(port, joystick.fire);
+        }
+    }
+
+    // Connects the pot input of each Genesis pad to the button its select line picks. A pressed
+    // button ties the input to +5V, so it reads HIGH whenever the capacitor isn't grounded.
+    fn update_genesis_pads(&mut self) {
+        for port in 0..2 {
+            if self.riot.controller(port) == Controller::Genesis {
+                let pressed = self.genesis_pads[port].pot_pressed(self.riot.genesis_select(port));
+                self.tia.set_paddle(2 * port + 1, pressed.then_some(0));
+            }
+        }
+    }
 }
 
 impl AtariBus {
-    pub fn new(tia: SharedTIA, riot: SharedRIOT, rom: Vec<u8>) -> Self {
-        Self { rom, tia, riot }
+    pub fn describe(&self, address: u16) -> MemoryRegion {
+        MemoryRegion::describe(address, self.rom.len())
+    }
+
+    // Maps a 4K cartridge address onto the ROM. A 2K ROM leaves A11 unconnected, so it appears twice
+    // in the cartridge space. ROM sizes are validated on load to be powers of two.
+    fn rom_offset(&self, address: usize) -> usize {
+        address & (self.rom.len() - 1)
+    }
+}
+
+impl AtariBus {
+    // Returns what a read of `address` would, without its side effects: the RIOT's interrupt flags
+    // are left alone, and invalid addresses read as 0 without being counted.
+    pub fn peek(&self, address: u16) -> u8 {
+        match MemoryMirrors::from(address, Operation::Read) {
+            Ok(MemoryMirrors::Cartridge(address)) => self.rom[self.rom_offset(address)],
+            Ok(MemoryMirrors::PiaIO(address)) => self.riot.peek(address),
+            Ok(MemoryMirrors::PiaRam(address)) => self.riot.peek(address),
+            Ok(MemoryMirrors::TiaRead(register)) => self.read_tia(address, register),
+            Err(_) => 0,
+            _ => unreachable!(),
+        }
+    }
+
+    // Records a register warning for reads of addresses that are only meant to be written
+    fn check_read(&mut self, address: u16) {
+        use PiaAddress::*;
+        let kind = match MemoryMirrors::from(address, Operation::Read) {
+            // The read registers are conventionally addressed at $00-$0D or $30-$3D
+            Ok(MemoryMirrors::TiaRead(_)) if (0x10..0x30).contains(&(address & 0x3f)) => {
+                TiaWriteAddress::try_from(address & 0x3f)
+                    .ok()
+                    .map(RegisterWarningKind::ReadOfWriteRegister)
+            }
+            Ok(MemoryMirrors::PiaIO(register @ (TIM1T | TIM8T | TIM64T | T1024T))) => {
+                Some(RegisterWarningKind::ReadOfTimerInterval(register))
+            }
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            self.diagnostics.register_warning(address, kind);
+        }
+    }
+
+    fn read_tia(&self, address: u16, register: TiaReadAddress) -> u8 {
+        // Bits the TIA doesn't drive keep whatever was last on the data bus. We model that as the
+        // low bits of the address, which is what's left there by the usual zero page
+        // `LDA CXxx`-style read.
+        let driven = register.driven_bits();
+        let val = self.tia.read(register);
+        (val & driven) | (address as u8 & !driven)
     }
 }
 
 impl Bus for AtariBus {
     fn read(&mut self, address: u16) -> u8 {
+        if self.diagnostics.register_warnings {
+            self.check_read(address);
+        }
+
         match MemoryMirrors::from(address, Operation::Read) {
-            Ok(MemoryMirrors::Cartridge(address)) => self.rom[address],
-            Ok(MemoryMirrors::PiaIO(address)) => self.riot.borrow_mut().read(address),
-            Ok(MemoryMirrors::PiaRam(address)) => self.riot.borrow_mut().read(address),
-            Ok(MemoryMirrors::TiaRead(address)) => self.tia.borrow_mut().read(address),
+            Ok(MemoryMirrors::Cartridge(address)) => self.rom[self.rom_offset(address)],
+            Ok(MemoryMirrors::PiaIO(address)) => self.riot.read(address),
+            Ok(MemoryMirrors::PiaRam(address)) => self.riot.read(address),
+            Ok(MemoryMirrors::TiaRead(register)) => self.read_tia(address, register),
             Err(e) => {
-                error!("{}", e);
+                self.diagnostics.invalid_access(address, e);
                 0
             }
             _ => unreachable!(),
@@ -46,14 +347,222 @@ impl Bus for AtariBus {
 
     fn write(&mut self, address: u16, val: u8) {
         match MemoryMirrors::from(address, Operation::Write) {
-            Ok(MemoryMirrors::Cartridge(address)) => self.rom[address] = val,
-            Ok(MemoryMirrors::PiaIO(address)) => self.riot.borrow_mut().write(address, val),
-            Ok(MemoryMirrors::PiaRam(address)) => self.riot.borrow_mut().write(address, val),
-            Ok(MemoryMirrors::TiaWrite(address)) => self.tia.borrow_mut().write(address, val),
-            Err(e) => error!("{}", e),
+            Ok(MemoryMirrors::Cartridge(address)) => {
+                let offset = self.rom_offset(address);
+                self.rom[offset] = val;
+            }
+            Ok(MemoryMirrors::PiaIO(register)) => {
+                if self.diagnostics.register_warnings
+                    && matches!(register, PiaAddress::SWCHB | PiaAddress::SWBCNT)
+                {
+                    self.diagnostics.register_warning(
+                        address,
+                        RegisterWarningKind::WriteToConsoleSwitches(register),
+                    );
+                }
+                self.riot.write(register, val);
+                // The game may have moved a Genesis pad's select line
+                self.update_controllers();
+            }
+            Ok(MemoryMirrors::PiaRam(address)) => self.riot.write(address, val),
+            Ok(MemoryMirrors::TiaWrite(address)) => {
+                self.tia.write(address, val);
+                // Or switched the joysticks QuadTaris show
+                if address == TiaWriteAddress::VBLANK {
+                    self.update_controllers();
+                }
+            }
+            Err(e) => self.diagnostics.invalid_access(address, e),
             _ => {
                 unreachable!();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Log, Metadata, Record};
+    use std::{
+        sync::{Mutex, Once},
+        thread::{self, ThreadId},
+    };
+
+    // Captures log messages along with the thread they were logged from, so that tests running
+    // in parallel don't see each other's messages.
+    struct CapturingLogger;
+
+    static LOGGER: CapturingLogger = CapturingLogger;
+    static INIT_LOGGER: Once = Once::new();
+    static MESSAGES: Mutex<Vec<(ThreadId, String)>> = Mutex::new(Vec::new());
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let message = format!("{}", record.args());
+            MESSAGES
+                .lock()
+                .unwrap()
+                .push((thread::current().id(), message));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn captured_messages() -> Vec<String> {
+        let id = thread::current().id();
+        MESSAGES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(thread, _)| *thread == id)
+            .map(|(_, message)| message.clone())
+            .collect()
+    }
+
+    fn bus() -> AtariBus {
+        INIT_LOGGER.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+
+        AtariBus::new(TIA::new(), RIOT::new(), Diagnostics::new(), vec![0; 4096])
+    }
+
+    #[test]
+    fn undriven_tia_read_bits_float() {
+        let mut bus = bus();
+
+        // No collisions yet, so only the floating bits are set
+        assert_eq!(bus.read(0x0007), 0x07);
+        assert_eq!(bus.read(0x0017), 0x17);
+        assert_eq!(bus.read(0x0036), 0x36);
+
+        // Overlap both players to set the P0-P1 collision bit of CXPPMM
+        bus.write(0x001b, 0xff);
+        bus.write(0x001c, 0xff);
+        for _ in 0..2 * 228 {
+            bus.tia.clock();
+        }
+
+        assert_eq!(bus.read(0x0007), 0x80 | 0x07);
+        assert_eq!(bus.read(0x0027), 0x80 | 0x27);
+        assert_eq!(bus.read(0x0037), 0x80 | 0x37);
+    }
+
+    #[test]
+    fn invalid_accesses_are_logged_once_per_address() {
+        let mut bus = bus();
+
+        for _ in 0..1000 {
+            bus.write(0x002d, 0);
+            bus.read(0x0290);
+        }
+
+        assert_eq!(bus.diagnostics().invalid_accesses(), 2000);
+        assert_eq!(captured_messages().len(), 2);
+    }
+
+    #[test]
+    fn invalid_access_log_limit_is_configurable() {
+        let mut bus = bus();
+        bus.diagnostics_mut().set_log_limit(5);
+
+        for _ in 0..1000 {
+            bus.write(0x002e, 0);
+        }
+
+        assert_eq!(bus.diagnostics().invalid_accesses(), 1000);
+        assert_eq!(captured_messages().len(), 5);
+    }
+
+    #[test]
+    fn tia_mirror_writes_reach_registers() {
+        let mut bus = bus();
+
+        // COLUBK through a few of its mirrors
+        for (address, color) in [(0x0049, 0x1e), (0x0109, 0x2e), (0x0a49, 0x3e)] {
+            bus.write(address, color);
+            assert_eq!(bus.tia().colors().colubk, color);
+        }
+        assert_eq!(bus.diagnostics().invalid_accesses(), 0);
+
+        // While the unused addresses after CXCLR are still reported on every mirror
+        bus.write(0x006d, 0);
+        bus.write(0x017f, 0);
+        assert_eq!(bus.diagnostics().invalid_accesses(), 2);
+    }
+
+    #[test]
+    fn genesis_select_line_picks_button() {
+        let mut bus = bus();
+        bus.set_controller(0, Controller::Genesis);
+        bus.set_genesis_button(0, GenesisButton::C, true);
+        // INPT1, with the floating bits of the address masked off
+        let inpt1 = |bus: &mut AtariBus| bus.read(0x0009) & 0x80;
+
+        // The select line is pulled high until the game drives it, so C is read
+        assert_eq!(inpt1(&mut bus), 0x80);
+
+        // Driving the up pin low as an output selects A, which isn't pressed
+        bus.write(0x0281, 0x10);
+        bus.write(0x0280, 0x00);
+        assert_eq!(inpt1(&mut bus), 0x00);
+        bus.set_genesis_button(0, GenesisButton::A, true);
+        bus.set_genesis_button(0, GenesisButton::C, false);
+        assert_eq!(inpt1(&mut bus), 0x80);
+
+        // And driving it high again selects C
+        bus.write(0x0280, 0x10);
+        assert_eq!(inpt1(&mut bus), 0x00);
+
+        // B is the fire button
+        bus.set_genesis_button(0, GenesisButton::B, true);
+        assert_eq!(bus.read(0x000c) & 0x80, 0x00);
+        bus.set_genesis_button(0, GenesisButton::B, false);
+        assert_eq!(bus.read(0x000c) & 0x80, 0x80);
+    }
+
+    #[test]
+    fn quadtari_switches_joysticks_on_vblank() {
+        let mut bus = bus();
+        bus.set_controller(0, Controller::QuadTari);
+        bus.set_joystick(0, Direction::Up, true);
+        bus.set_joystick(2, Direction::Down, true);
+        bus.set_joystick_fire(2, true);
+        // The left port's directions, and its fire button through INPT4
+        let left = |bus: &mut AtariBus| (bus.read(0x0280) >> 4, bus.read(0x000c) & 0x80);
+
+        bus.write(0x0001, 0x80);
+        assert_eq!(left(&mut bus), (0b1110, 0x80));
+        bus.write(0x0001, 0x00);
+        assert_eq!(left(&mut bus), (0b1101, 0x00));
+
+        // A joystick plugged straight into the right port isn't switched
+        bus.set_joystick(1, Direction::Left, true);
+        bus.set_joystick(3, Direction::Right, true);
+        assert_eq!(bus.read(0x0280) & 0x0f, 0b1011);
+        bus.write(0x0001, 0x80);
+        assert_eq!(bus.read(0x0280) & 0x0f, 0b1011);
+    }
+
+    #[test]
+    fn half_size_cartridge_is_mirrored() {
+        let rom = (0..2048).map(|i| (i ^ (i >> 8)) as u8).collect();
+        let mut bus = AtariBus::new(TIA::new(), RIOT::new(), Diagnostics::new(), rom);
+
+        for offset in 0..2048 {
+            let expected = (offset ^ (offset >> 8)) as u8;
+            assert_eq!(bus.read(0x1000 + offset), expected);
+            assert_eq!(bus.read(0x1800 + offset), expected);
+        }
+
+        // The reset vector at $1FFC comes from the end of the 2K image
+        assert_eq!(bus.read(0x1ffc), bus.read(0x17fc));
+        assert_eq!(bus.read(0xfffd), bus.read(0xf7fd));
+    }
+}
@@ -0,0 +1,136 @@
+/// What is plugged into a controller port.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Controller {
+    /// A standard joystick, driven through `EmulatorCore::set_joystick`, or the `KeyEvent` methods
+    /// for the left one.
+    #[default]
+    Joystick,
+    /// An Amiga mouse, or a trackball switched to Amiga mode, driven through
+    /// `EmulatorCore::mouse_move`. Its button reads like the joystick's fire button.
+    AmigaMouse,
+    /// A driving controller, as used by Indy 500, turned through `EmulatorCore::driving_turn`. Its
+    /// wheel's encoder is read on the up and down pins, and its button like the joystick's fire
+    /// button.
+    Driving,
+    /// A Sega Genesis 3-button pad. Its directions are read like a joystick's, and its buttons are
+    /// driven through `EmulatorCore::set_genesis_button`.
+    Genesis,
+    /// A QuadTari adapter with two joysticks plugged into it, driven through
+    /// `EmulatorCore::set_joystick`. The adapter shows the first joystick to the console while D7 of
+    /// VBLANK is set, and the second while it is clear.
+    QuadTari,
+}
+
+/// A joystick direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    // The bit of the direction's pin, within a port's half of SWCHA
+    pub(crate) fn bit(self) -> u8 {
+        match self {
+            Direction::Up => 0b0001,
+            Direction::Down => 0b0010,
+            Direction::Left => 0b0100,
+            Direction::Right => 0b1000,
+        }
+    }
+}
+
+// The switches of a joystick, with a `Direction` bit set for each direction pressed
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Joystick {
+    pub directions: u8,
+    pub fire: bool,
+}
+
+/// A button of a Sega Genesis pad. B is read through the port's fire input (INPT4 or INPT5), while
+/// A and C share its second pot input (INPT1 or INPT3): C is read there while the pad's select
+/// line is high, and A while it is low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenesisButton {
+    A,
+    B,
+    C,
+}
+
+// The buttons of a Genesis pad that share its pot input
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct GenesisPad {
+    pub a: bool,
+    pub c: bool,
+}
+
+impl GenesisPad {
+    // Whether the button the select line picks is pressed
+    pub fn pot_pressed(&self, select: bool) -> bool {
+        if select {
+            self.c
+        } else {
+            self.a
+        }
+    }
+}
+
+// The order of the two bits a quadrature encoder outputs as it turns forwards. Only one bit changes
+// per step, so the direction can be told from which one did.
+const GRAY_CODE: [u8; 4] = [0b00, 0b01, 0b11, 0b10];
+
+// A quadrature encoder, like the ones in driving controllers, mice and trackballs. Movement is
+// queued up and played out one step at a time, since a game polling the port only sees the
+// direction of a single step between two reads.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Quadrature {
+    phase: usize,
+    pending: i32,
+}
+
+impl Quadrature {
+    pub fn move_by(&mut self, delta: i32) {
+        self.pending = self.pending.saturating_add(delta);
+    }
+
+    // Takes a single step towards the queued up movement, if there is any
+    pub fn step(&mut self) {
+        match self.pending.signum() {
+            1 => self.phase = (self.phase + 1) % 4,
+            -1 => self.phase = (self.phase + 3) % 4,
+            _ => return,
+        }
+        self.pending -= self.pending.signum();
+    }
+
+    pub fn bits(&self) -> u8 {
+        GRAY_CODE[self.phase]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_follow_gray_code() {
+        let mut encoder = Quadrature::default();
+        encoder.move_by(5);
+
+        let mut seen = vec![encoder.bits()];
+        for _ in 0..6 {
+            encoder.step();
+            seen.push(encoder.bits());
+        }
+        // The last step has nothing left to play out
+        assert_eq!(seen, [0b00, 0b01, 0b11, 0b10, 0b00, 0b01, 0b01]);
+
+        encoder.move_by(-2);
+        encoder.step();
+        assert_eq!(encoder.bits(), 0b00);
+        encoder.step();
+        assert_eq!(encoder.bits(), 0b10);
+    }
+}
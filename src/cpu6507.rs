@@ -1,18 +1,14 @@
 use crate::bus::Bus;
 use crate::opcode::{AddressingMode, Instruction, Opcode, OPCODES};
-use log::{debug, info};
-use std::{env, process};
+use log::{debug, error, info};
 
 const STACK_INIT: u8 = 0xff;
 const LOW_NIBBLE_MASK: u16 = 0x0F;
 const HIGH_NIBBLE_MASK: u16 = 0xF0;
 
-lazy_static::lazy_static! {
-    static ref CPU6507_DEBUG: bool = match env::var("CPU6507_DEBUG") {
-        Ok(val) => !val.is_empty() && val != "0",
-        Err(_) => false,
-    };
-}
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
 
 fn pages_differ(addr_a: u16, addr_b: u16) -> bool {
     (addr_a & 0xff00) != (addr_b & 0xff00)
@@ -21,6 +17,7 @@ fn pages_differ(addr_a: u16, addr_b: u16) -> bool {
 #[allow(dead_code)]
 mod status {
     use modular_bitfield::bitfield;
+    #[derive(Clone, Copy)]
     #[bitfield(bits = 8)]
     pub(crate) struct StatusRegisterFlags {
         pub c: bool, // Carry flag (1 if last operation resulted in carry, borrow, or extend beyond MSB)
@@ -35,8 +32,39 @@ mod status {
 }
 use status::StatusRegisterFlags;
 
-pub(crate) struct CPU6507 {
-    bus: Box<dyn Bus>,
+// Executes a decoded instruction, given its operand address and addressing mode
+type Handler<B> = fn(&mut CPU6507<B>, u16, AddressingMode);
+
+/// The CPU's registers and the state of the instruction in progress, taken with
+/// `EmulatorCore::cpu_snapshot`. Memory isn't included, so restoring a snapshot doesn't undo
+/// what the CPU has written since.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuSnapshot {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub cycles: u64,
+    pub instructions: u64,
+
+    // The instruction in progress, which is executed on its last cycle
+    current_pc: u16,
+    current_instruction: Option<Instruction>,
+    current_opcode: u8,
+    current_addr: u16,
+    current_addr_mode: AddressingMode,
+    current_cycles: u64,
+
+    nmi_pending: bool,
+    irq_asserted: bool,
+    jammed: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct CPU6507<B: Bus> {
+    bus: B,
 
     // Main registers
     pub a: u8, // Accumulator
@@ -54,27 +82,54 @@ pub(crate) struct CPU6507 {
 
     // Total number of cycles executed
     cycles: u64,
+    // Number of instructions completed since reset
+    instructions: u64,
 
+    // Address of the instruction in progress
+    current_pc: u16,
     current_instruction: Option<Instruction>,
+    current_opcode: u8,
     current_addr: u16,
     current_addr_mode: AddressingMode,
     current_cycles: u64,
+
+    // Set on the NMI edge, serviced at the next instruction boundary
+    nmi_pending: bool,
+    // Level of the IRQ line, serviced at every instruction boundary while I is clear
+    irq_asserted: bool,
+    // Set by a JAM opcode. The CPU stops until it's reset.
+    jammed: bool,
+
+    // Whether JMP ($xxFF) reads the high byte from the next page, as the 65C02 does
+    cmos_jmp: bool,
 }
 
-impl Bus for CPU6507 {
+impl<B: Bus> Bus for CPU6507<B> {
     fn read(&mut self, addr: u16) -> u8 {
         // The 6507 only had 13 address lines connected.
-        self.bus.read(addr & 0x1fff)
+        self.bus.read(addr & B::ADDRESS_MASK)
     }
 
     fn write(&mut self, addr: u16, val: u8) {
         // The 6507 only had 13 address lines connected.
-        self.bus.write(addr & 0x1fff, val);
+        self.bus.write(addr & B::ADDRESS_MASK, val);
     }
 }
 
-impl CPU6507 {
-    pub fn new(bus: Box<dyn Bus>) -> Self {
+impl<B: Bus> CPU6507<B> {
+    // Handlers indexed by opcode. It is built from OPCODES at compile time, so the two can't get
+    // out of sync.
+    const DISPATCH: [Handler<B>; 256] = {
+        let mut table: [Handler<B>; 256] = [|cpu, _, _| cpu.unsupported(); 256];
+        let mut i = 0;
+        while i < 256 {
+            table[i] = Self::handler(OPCODES[i].0);
+            i += 1;
+        }
+        table
+    };
+
+    pub fn new(bus: B) -> Self {
         Self {
             bus,
 
@@ -89,19 +144,110 @@ impl CPU6507 {
             sp: STACK_INIT,
 
             cycles: 0,
+            instructions: 0,
 
+            current_pc: 0x0000,
             current_instruction: None,
+            current_opcode: 0,
             current_addr: 0x0000,
             current_addr_mode: AddressingMode::Accumulator,
             current_cycles: 0,
+
+            nmi_pending: false,
+            irq_asserted: false,
+            jammed: false,
+
+            cmos_jmp: false,
         }
     }
 
-    pub fn reset(&mut self) {
-        let lo = self.read(0xFFFC) as u16;
-        let hi = self.read(0xFFFD) as u16;
-        let addr = (hi << 8) | lo;
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions
+    }
+
+    // Number of cycles clocked since reset. `cycles` already includes the rest of the instruction
+    // in progress, so those are taken back off.
+    pub fn total_cycles(&self) -> u64 {
+        self.cycles.wrapping_sub(self.current_cycles)
+    }
+
+    // Address of the instruction in progress, or of the last one once it has been executed
+    pub fn instruction_address(&self) -> u16 {
+        self.current_pc
+    }
+
+    // Whether the CPU is partway through an instruction, rather than about to fetch the next one
+    pub fn instruction_in_progress(&self) -> bool {
+        self.current_cycles != 0
+    }
+
+    pub fn jammed(&self) -> bool {
+        self.jammed
+    }
+
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            p: self.flags(),
+            sp: self.sp,
+            pc: self.pc,
+            cycles: self.cycles,
+            instructions: self.instructions,
+
+            current_pc: self.current_pc,
+            current_instruction: self.current_instruction,
+            current_opcode: self.current_opcode,
+            current_addr: self.current_addr,
+            current_addr_mode: self.current_addr_mode,
+            current_cycles: self.current_cycles,
+
+            nmi_pending: self.nmi_pending,
+            irq_asserted: self.irq_asserted,
+            jammed: self.jammed,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: CpuSnapshot) {
+        self.a = snapshot.a;
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.set_flags(snapshot.p);
+        self.sp = snapshot.sp;
+        self.pc = snapshot.pc;
+        self.cycles = snapshot.cycles;
+        self.instructions = snapshot.instructions;
+
+        self.current_pc = snapshot.current_pc;
+        self.current_instruction = snapshot.current_instruction;
+        self.current_opcode = snapshot.current_opcode;
+        self.current_addr = snapshot.current_addr;
+        self.current_addr_mode = snapshot.current_addr_mode;
+        self.current_cycles = snapshot.current_cycles;
+
+        self.nmi_pending = snapshot.nmi_pending;
+        self.irq_asserted = snapshot.irq_asserted;
+        self.jammed = snapshot.jammed;
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    // Continues execution at `addr`, as if jumped to. An instruction in progress is abandoned.
+    pub fn set_pc(&mut self, addr: u16) {
         self.pc = addr;
+        self.current_instruction = None;
+        self.current_cycles = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.pc = self.read_vector(RESET_VECTOR);
         info!("PC: 0x{:04X}", self.pc);
 
         self.set_flags(0x24);
@@ -112,6 +258,42 @@ impl CPU6507 {
         self.y = 0;
 
         self.cycles = 0;
+        self.instructions = 0;
+        self.nmi_pending = false;
+        self.irq_asserted = false;
+        self.jammed = false;
+    }
+
+    /// Signals a falling edge on the NMI line. The 6507 in the Atari doesn't have an NMI pin, but
+    /// this keeps the core usable as a plain 6502. NMI is serviced after the current instruction
+    /// regardless of the I flag, and repeated edges before then only cause a single interrupt.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Drives the IRQ line. Like NMI there is no IRQ pin on the 6507. The line is level triggered,
+    /// so the interrupt is taken at every instruction boundary for as long as it stays asserted
+    /// and the I flag is clear. A pending NMI always wins over an IRQ.
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_asserted = asserted;
+    }
+
+    /// Fixes the indirect JMP page wrap, see `calculate_indirect_address`. Only the target address
+    /// changes, the instruction keeps its NMOS timing.
+    pub fn set_cmos_jmp(&mut self, enabled: bool) {
+        self.cmos_jmp = enabled;
+    }
+
+    fn read_vector(&mut self, vector: u16) -> u16 {
+        let lo = self.read(vector) as u16;
+        let hi = self.read(vector + 1) as u16;
+        (hi << 8) | lo
+    }
+
+    // Only used by the debug HUD, which needs the `image` feature
+    #[allow(dead_code)]
+    pub fn sp(&self) -> u8 {
+        self.sp
     }
 
     fn calculate_absolute_address(&mut self, pc: u16) -> u16 {
@@ -120,16 +302,26 @@ impl CPU6507 {
         (hi << 8) | lo
     }
 
+    // The NMOS 6502 doesn't carry into the high byte of the pointer, so JMP ($xxFF) takes the high
+    // byte of the target from $xx00. The 65C02 fixed this.
     fn calculate_indirect_address(&mut self, addr: u16) -> u16 {
         let lo = self.read(addr) as u16;
-        let hi = if addr & 0xff == 0xff {
+        let hi = if addr & 0xff == 0xff && !self.cmos_jmp {
             self.read(addr & 0xff00) as u16
         } else {
-            self.read(addr + 1) as u16
+            self.read(addr.wrapping_add(1)) as u16
         };
         (hi << 8) | lo
     }
 
+    // Reads a pointer from the zero page. A pointer at $FF takes its high byte from $00, on the
+    // 65C02 as well.
+    fn calculate_zero_page_pointer(&mut self, addr: u8) -> u16 {
+        let lo = self.read(addr as u16) as u16;
+        let hi = self.read(addr.wrapping_add(1) as u16) as u16;
+        (hi << 8) | lo
+    }
+
     fn get_data(&mut self, addr_mode: &AddressingMode) -> (u16, bool) {
         let pc = self.pc;
         let next_pc = self.pc + addr_mode.n_bytes() as u16;
@@ -185,13 +377,12 @@ impl CPU6507 {
             }
             AddressingMode::IndexedIndirect => {
                 let lo = self.read(pc + 1);
-                let addr = lo.wrapping_add(self.x) as u16;
-                let addr = self.calculate_indirect_address(addr);
+                let addr = self.calculate_zero_page_pointer(lo.wrapping_add(self.x));
                 (addr, false)
             }
             AddressingMode::IndirectIndexed => {
-                let addr = self.read(pc + 1) as u16;
-                let addr = self.calculate_indirect_address(addr);
+                let lo = self.read(pc + 1);
+                let addr = self.calculate_zero_page_pointer(lo);
                 let n_addr = addr.wrapping_add(self.y as u16);
                 (n_addr, pages_differ(addr, n_addr))
             }
@@ -259,14 +450,19 @@ impl CPU6507 {
         self.flags.set_z(val == 0);
     }
 
+    fn add_cycles(&mut self, n: u64) {
+        // `current_cycles` and `cycles` must always move together, otherwise the total drifts away
+        // from the number of times the CPU has actually been clocked.
+        self.current_cycles += n;
+        self.cycles = self.cycles.wrapping_add(n);
+    }
+
     fn add_branch_cycles(&mut self, pc: u16, addr: u16) {
-        self.current_cycles += 1;
-        self.cycles += 1;
+        self.add_cycles(1);
 
         // It costs an extra cycle to branch to a different page.
         if (pc & 0xff00) != (addr & 0xff00) {
-            self.current_cycles += 1;
-            self.cycles += 1;
+            self.add_cycles(1);
         }
     }
 
@@ -288,6 +484,7 @@ impl CPU6507 {
 
         // Update CPU state
         self.current_instruction = Some(*inst);
+        self.current_opcode = opcode;
         self.current_addr = addr;
         self.current_addr_mode = *addr_mode;
 
@@ -296,90 +493,112 @@ impl CPU6507 {
     }
 
     fn execute(&mut self) {
-        if let Some(inst) = self.current_instruction {
-            let addr = self.current_addr;
-            let addr_mode = self.current_addr_mode;
-
-            match inst {
-                Instruction::ADC => self.adc(addr),
-                Instruction::ANC => self.anc(addr),
-                Instruction::AND => self.and(addr),
-                Instruction::ASL => self.asl(addr, addr_mode),
-                Instruction::BCC => self.bcc(addr),
-                Instruction::BCS => self.bcs(addr),
-                Instruction::BEQ => self.beq(addr),
-                Instruction::BIT => self.bit(addr),
-                Instruction::BMI => self.bmi(addr),
-                Instruction::BNE => self.bne(addr),
-                Instruction::BPL => self.bpl(addr),
-                Instruction::BRK => self.brk(),
-                Instruction::BVC => self.bvc(addr),
-                Instruction::BVS => self.bvs(addr),
-                Instruction::CLC => self.clc(),
-                Instruction::CLD => self.cld(),
-                Instruction::CLI => self.cli(),
-                Instruction::CLV => self.clv(),
-                Instruction::CMP => self.cmp(addr),
-                Instruction::CPX => self.cpx(addr),
-                Instruction::CPY => self.cpy(addr),
-                Instruction::DCP => self.dcp(addr),
-                Instruction::DEC => self.dec(addr),
-                Instruction::DEX => self.dex(),
-                Instruction::DEY => self.dey(),
-                Instruction::EOR => self.eor(addr),
-                Instruction::INC => self.inc(addr),
-                Instruction::INX => self.inx(),
-                Instruction::INY => self.iny(),
-                Instruction::ISB => self.isb(addr),
-                Instruction::JAM => self.jam(),
-                Instruction::JMP => self.jmp(addr),
-                Instruction::JSR => self.jsr(addr),
-                Instruction::LAX => self.lax(addr),
-                Instruction::LDA => self.lda(addr),
-                Instruction::LDX => self.ldx(addr),
-                Instruction::LDY => self.ldy(addr),
-                Instruction::LSR => self.lsr(addr, addr_mode),
-                Instruction::NOP => self.nop(),
-                Instruction::ORA => self.ora(addr),
-                Instruction::PHA => self.pha(),
-                Instruction::PHP => self.php(),
-                Instruction::PLA => self.pla(),
-                Instruction::PLP => self.plp(),
-                Instruction::RLA => self.rla(addr, addr_mode),
-                Instruction::ROL => self.rol(addr, addr_mode),
-                Instruction::ROR => self.ror(addr, addr_mode),
-                Instruction::RRA => self.rra(addr, addr_mode),
-                Instruction::RTI => self.rti(),
-                Instruction::RTS => self.rts(),
-                Instruction::SAX => self.sax(addr),
-                Instruction::SBC => self.sbc(addr),
-                Instruction::SEC => self.sec(),
-                Instruction::SED => self.sed(),
-                Instruction::SEI => self.sei(),
-                Instruction::SLO => self.slo(addr, addr_mode),
-                Instruction::SRE => self.sre(addr, addr_mode),
-                Instruction::STA => self.sta(addr),
-                Instruction::STX => self.stx(addr),
-                Instruction::STY => self.sty(addr),
-                Instruction::TAX => self.tax(),
-                Instruction::TAY => self.tay(),
-                Instruction::TSX => self.tsx(),
-                Instruction::TXA => self.txa(),
-                Instruction::TXS => self.txs(),
-                Instruction::TYA => self.tya(),
-                _ => panic!("unsupported instruction {:?}", inst),
-            }
+        if self.current_instruction.take().is_some() {
+            let handler = Self::DISPATCH[self.current_opcode as usize];
+            handler(self, self.current_addr, self.current_addr_mode);
 
-            self.current_instruction = None;
+            self.instructions += 1;
         }
     }
 
+    // Maps an instruction to the method implementing it
+    const fn handler(inst: Instruction) -> Handler<B> {
+        match inst {
+            Instruction::ADC => |cpu, addr, _| cpu.adc(addr),
+            Instruction::ANC => |cpu, addr, _| cpu.anc(addr),
+            Instruction::AND => |cpu, addr, _| cpu.and(addr),
+            Instruction::ASL => |cpu, addr, addr_mode| cpu.asl(addr, addr_mode),
+            Instruction::BCC => |cpu, addr, _| cpu.bcc(addr),
+            Instruction::BCS => |cpu, addr, _| cpu.bcs(addr),
+            Instruction::BEQ => |cpu, addr, _| cpu.beq(addr),
+            Instruction::BIT => |cpu, addr, _| cpu.bit(addr),
+            Instruction::BMI => |cpu, addr, _| cpu.bmi(addr),
+            Instruction::BNE => |cpu, addr, _| cpu.bne(addr),
+            Instruction::BPL => |cpu, addr, _| cpu.bpl(addr),
+            Instruction::BRK => |cpu, _, _| cpu.brk(),
+            Instruction::BVC => |cpu, addr, _| cpu.bvc(addr),
+            Instruction::BVS => |cpu, addr, _| cpu.bvs(addr),
+            Instruction::CLC => |cpu, _, _| cpu.clc(),
+            Instruction::CLD => |cpu, _, _| cpu.cld(),
+            Instruction::CLI => |cpu, _, _| cpu.cli(),
+            Instruction::CLV => |cpu, _, _| cpu.clv(),
+            Instruction::CMP => |cpu, addr, _| cpu.cmp(addr),
+            Instruction::CPX => |cpu, addr, _| cpu.cpx(addr),
+            Instruction::CPY => |cpu, addr, _| cpu.cpy(addr),
+            Instruction::DCP => |cpu, addr, _| cpu.dcp(addr),
+            Instruction::DEC => |cpu, addr, _| cpu.dec(addr),
+            Instruction::DEX => |cpu, _, _| cpu.dex(),
+            Instruction::DEY => |cpu, _, _| cpu.dey(),
+            Instruction::EOR => |cpu, addr, _| cpu.eor(addr),
+            Instruction::INC => |cpu, addr, _| cpu.inc(addr),
+            Instruction::INX => |cpu, _, _| cpu.inx(),
+            Instruction::INY => |cpu, _, _| cpu.iny(),
+            Instruction::ISB => |cpu, addr, _| cpu.isb(addr),
+            Instruction::JAM => |cpu, _, _| cpu.jam(),
+            Instruction::JMP => |cpu, addr, _| cpu.jmp(addr),
+            Instruction::JSR => |cpu, addr, _| cpu.jsr(addr),
+            Instruction::LAX => |cpu, addr, _| cpu.lax(addr),
+            Instruction::LDA => |cpu, addr, _| cpu.lda(addr),
+            Instruction::LDX => |cpu, addr, _| cpu.ldx(addr),
+            Instruction::LDY => |cpu, addr, _| cpu.ldy(addr),
+            Instruction::LSR => |cpu, addr, addr_mode| cpu.lsr(addr, addr_mode),
+            Instruction::NOP => |cpu, _, _| cpu.nop(),
+            Instruction::ORA => |cpu, addr, _| cpu.ora(addr),
+            Instruction::PHA => |cpu, _, _| cpu.pha(),
+            Instruction::PHP => |cpu, _, _| cpu.php(),
+            Instruction::PLA => |cpu, _, _| cpu.pla(),
+            Instruction::PLP => |cpu, _, _| cpu.plp(),
+            Instruction::RLA => |cpu, addr, addr_mode| cpu.rla(addr, addr_mode),
+            Instruction::ROL => |cpu, addr, addr_mode| cpu.rol(addr, addr_mode),
+            Instruction::ROR => |cpu, addr, addr_mode| cpu.ror(addr, addr_mode),
+            Instruction::RRA => |cpu, addr, addr_mode| cpu.rra(addr, addr_mode),
+            Instruction::RTI => |cpu, _, _| cpu.rti(),
+            Instruction::RTS => |cpu, _, _| cpu.rts(),
+            Instruction::SAX => |cpu, addr, _| cpu.sax(addr),
+            Instruction::SBC => |cpu, addr, _| cpu.sbc(addr),
+            Instruction::SEC => |cpu, _, _| cpu.sec(),
+            Instruction::SED => |cpu, _, _| cpu.sed(),
+            Instruction::SEI => |cpu, _, _| cpu.sei(),
+            Instruction::SLO => |cpu, addr, addr_mode| cpu.slo(addr, addr_mode),
+            Instruction::SRE => |cpu, addr, addr_mode| cpu.sre(addr, addr_mode),
+            Instruction::STA => |cpu, addr, _| cpu.sta(addr),
+            Instruction::STX => |cpu, addr, _| cpu.stx(addr),
+            Instruction::STY => |cpu, addr, _| cpu.sty(addr),
+            Instruction::TAX => |cpu, _, _| cpu.tax(),
+            Instruction::TAY => |cpu, _, _| cpu.tay(),
+            Instruction::TSX => |cpu, _, _| cpu.tsx(),
+            Instruction::TXA => |cpu, _, _| cpu.txa(),
+            Instruction::TXS => |cpu, _, _| cpu.txs(),
+            Instruction::TYA => |cpu, _, _| cpu.tya(),
+            Instruction::None => |cpu, _, _| cpu.unsupported(),
+        }
+    }
+
+    fn unsupported(&mut self) {
+        let opcode = self.current_opcode;
+        panic!("unsupported instruction {:?}", OPCODES[opcode as usize].0);
+    }
+
     pub fn clock(&mut self) {
+        if self.jammed {
+            return;
+        }
+
         if self.current_cycles == 0 {
-            self.current_cycles += self.fetch_and_decode();
+            self.current_pc = self.pc;
+            let cycles = if self.nmi_pending {
+                self.nmi_pending = false;
+                self.interrupt(NMI_VECTOR)
+            } else if self.irq_asserted && !self.flags.i() {
+                self.interrupt(IRQ_VECTOR)
+            } else {
+                self.fetch_and_decode()
+            };
+            self.add_cycles(cycles);
         }
 
-        self.current_cycles -= 1;
+        // JAM takes no cycles, it's executed as soon as it's fetched
+        self.current_cycles = self.current_cycles.saturating_sub(1);
         if self.current_cycles == 0 {
             self.execute();
         }
@@ -391,26 +610,35 @@ impl CPU6507 {
 
     fn adc(&mut self, addr: u16) {
         let val = self.read(addr);
+        self.add(val);
+    }
 
+    // Adds `val` and the carry to the accumulator, honouring decimal mode. Shared by ADC and RRA.
+    fn add(&mut self, val: u8) {
         if self.flags.d() {
             self.adc_bcd(val);
         } else {
-            let n = (self.a as u16) + (val as u16) + (self.flags.c() as u16);
-            let a = (n & 0x00ff) as u8;
+            self.add_binary(val);
+        }
+    }
 
-            self.update_sz(a);
-            self.flags.set_c(n > 0xff);
+    // Binary mode ADC. Binary mode SBC is the same thing with the operand inverted.
+    fn add_binary(&mut self, val: u8) {
+        let n = (self.a as u16) + (val as u16) + (self.flags.c() as u16);
+        let a = (n & 0x00ff) as u8;
 
-            // The first condition checks if the sign of the accumulator and the
-            // the sign of value that we're adding are the same.
-            //
-            // The second condition checks if the result of the addition has a
-            // different sign to either of the values we added together.
-            self.flags
-                .set_v(((self.a ^ val) & 0x80 == 0) && ((self.a ^ a) & 0x80 != 0));
+        self.update_sz(a);
+        self.flags.set_c(n > 0xff);
 
-            self.a = a;
-        }
+        // The first condition checks if the sign of the accumulator and the
+        // the sign of value that we're adding are the same.
+        //
+        // The second condition checks if the result of the addition has a
+        // different sign to either of the values we added together.
+        self.flags
+            .set_v(((self.a ^ val) & 0x80 == 0) && ((self.a ^ a) & 0x80 != 0));
+
+        self.a = a;
     }
 
     fn adc_bcd(&mut self, val: u8) {
@@ -519,10 +747,30 @@ impl CPU6507 {
 
         self.flags.set_i(true);
 
-        let lo = self.read(0xFFFE) as u16;
-        let hi = self.read(0xFFFF) as u16;
-        let pc = (hi << 8) | lo;
-        self.pc = pc;
+        // An NMI arriving while BRK is executing hijacks its vector fetch. The pushed flags still
+        // have B set, which is how a handler can tell the two apart.
+        let vector = if self.nmi_pending {
+            self.nmi_pending = false;
+            NMI_VECTOR
+        } else {
+            IRQ_VECTOR
+        };
+        self.pc = self.read_vector(vector);
+    }
+
+    // Hardware interrupt sequence shared by NMI and IRQ
+    fn interrupt(&mut self, vector: u16) -> u64 {
+        let pc = self.pc;
+        self.stack_push16(pc);
+
+        // Unlike BRK, the B flag is pushed clear
+        let flags = (self.flags() & 0xef) | 0x20;
+        self.stack_push8(flags);
+
+        self.flags.set_i(true);
+        self.pc = self.read_vector(vector);
+
+        7
     }
 
     fn bvc(&mut self, addr: u16) {
@@ -734,27 +982,17 @@ impl CPU6507 {
 
     fn sbc(&mut self, addr: u16) {
         let val = self.read(addr);
+        self.subtract(val);
+    }
 
+    // Subtracts `val` and the borrow from the accumulator, honouring decimal mode. Shared by SBC
+    // and ISB.
+    fn subtract(&mut self, val: u8) {
         if self.flags.d() {
             // http://www.6502.org/tutorials/decimal_mode.html
             self.sbc_decimal(val);
         } else {
-            let val = !val;
-            let n = (self.a as u16) + (val as u16) + (self.flags.c() as u16);
-            let a = (n & 0x00ff) as u8;
-
-            self.update_sz(a);
-            self.flags.set_c(n > 0xff);
-
-            // The first condition checks if the sign of the accumulator and the
-            // the sign of value that we're adding are the same.
-            //
-            // The second condition checks if the result of the addition has a
-            // different sign to either of the values we added together.
-            self.flags
-                .set_v(((self.a ^ val) & 0x80 == 0) && ((self.a ^ a) & 0x80 != 0));
-
-            self.a = a;
+            self.add_binary(!val);
         }
     }
 
@@ -890,18 +1128,7 @@ impl CPU6507 {
         self.write(addr, n);
         self.update_sz(n);
 
-        // Copied from sbc
-        let val = n;
-        let n: i16 = (self.a as i16)
-            .wrapping_sub(val as i16)
-            .wrapping_sub(1 - self.flags.c() as i16);
-
-        let a = n as u8;
-        self.update_sz(a);
-        self.flags
-            .set_v(((self.a ^ val) & 0x80 > 0) && ((self.a ^ n as u8) & 0x80 > 0));
-        self.a = a;
-        self.flags.set_c(n >= 0);
+        self.subtract(n);
     }
 
     fn slo(&mut self, addr: u16, addr_mode: AddressingMode) {
@@ -976,18 +1203,483 @@ impl CPU6507 {
             _ => self.write(addr, n),
         };
 
-        // Copied from adc
-        let val = n;
-        let n = (val as u16) + (self.a as u16) + (self.flags.c() as u16);
-        let a = (n & 0xff) as u8;
-        self.update_sz(a);
-        self.flags.set_c(n > 0xff);
-        self.flags
-            .set_v(((self.a ^ val) & 0x80 == 0) && ((self.a ^ n as u8) & 0x80 > 0));
-        self.a = a;
+        self.add(n);
     }
 
+    // On the real chip, JAM locks up the CPU until it's reset
     fn jam(&mut self) {
-        process::exit(0);
+        error!("CPU jammed at {:04X}", self.current_pc);
+        self.jammed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat_bus::FlatBus;
+
+    struct TestBus {
+        mem: [u8; 0x2000],
+    }
+
+    impl Bus for TestBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.mem[address as usize]
+        }
+
+        fn write(&mut self, address: u16, val: u8) {
+            self.mem[address as usize] = val;
+        }
+    }
+
+    fn cpu_with_program(origin: u16, program: &[u8]) -> CPU6507<TestBus> {
+        let mut mem = [0; 0x2000];
+        let start = origin as usize & 0x1fff;
+        mem[start..start + program.len()].copy_from_slice(program);
+        mem[0x1ffc] = (origin & 0xff) as u8;
+        mem[0x1ffd] = (origin >> 8) as u8;
+
+        let mut cpu = CPU6507::new(TestBus { mem });
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn cycles_match_executed_opcodes() {
+        #[rustfmt::skip]
+        let program = [
+            0xA2, 0x40,       // 10F0: LDX #$40         2
+            0xCA,             // 10F2: DEX              2 * 64
+            0xD0, 0xFD,       // 10F3: BNE $10F2        3 * 63 + 2
+            0xA0, 0x20,       // 10F5: LDY #$20         2
+            0xB9, 0xF0, 0x10, // 10F7: LDA $10F0,Y      4 + 1 (page crossed)
+            0xF0, 0x10,       // 10FA: BEQ $110C        2 + 1 + 1 (page crossed)
+        ];
+        let mut cpu = cpu_with_program(0x10F0, &program);
+        cpu.bus.write(0x110C, 0xEA); // 110C: NOP       2
+
+        let expected = 2 + 2 * 64 + (3 * 63 + 2) + 2 + 5 + 4 + 2;
+        for n in 0..expected {
+            assert_eq!(cpu.total_cycles(), n);
+            cpu.clock();
+        }
+
+        assert_eq!(cpu.pc, 0x110D);
+        assert_eq!(cpu.current_cycles, 0);
+        assert_eq!(cpu.cycles, expected);
+        assert_eq!(cpu.total_cycles(), expected);
+        assert_eq!(cpu.instructions_executed(), 1 + 64 + 64 + 1 + 1 + 1 + 1);
+    }
+
+    #[test]
+    fn total_cycles_wraps_around() {
+        #[rustfmt::skip]
+        let program = [
+            0xCA,       // 1000: DEX    2
+            0xD0, 0xFD, // 1001: BNE    3
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+        let start = u64::MAX - 6;
+        cpu.cycles = start;
+
+        // The count rolls over in the middle of the second loop
+        for n in 0..15 {
+            assert_eq!(cpu.total_cycles(), start.wrapping_add(n));
+            cpu.clock();
+        }
+        assert_eq!(cpu.total_cycles(), 8);
+    }
+
+    #[test]
+    fn jam_halts_until_reset() {
+        #[rustfmt::skip]
+        let program = [
+            0xEA, // 1000: NOP
+            0x02, // 1001: JAM
+            0xEA, // 1002: NOP
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+        for _ in 0..10 {
+            cpu.clock();
+        }
+        assert!(cpu.jammed());
+        assert_eq!(cpu.pc, 0x1002);
+        assert_eq!(cpu.total_cycles(), 2);
+        assert_eq!(cpu.instructions_executed(), 2);
+
+        // Interrupts don't get through either
+        cpu.trigger_nmi();
+        cpu.clock();
+        assert_eq!(cpu.pc, 0x1002);
+
+        cpu.reset();
+        assert!(!cpu.jammed());
+        cpu.clock();
+        cpu.clock();
+        assert_eq!(cpu.instructions_executed(), 1);
+    }
+
+    #[test]
+    fn nmi_ignores_interrupt_disable() {
+        #[rustfmt::skip]
+        let program = [
+            0x78, // 1000: SEI
+            0xEA, // 1001: NOP
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+        cpu.bus.write(0x1ffa, 0x00);
+        cpu.bus.write(0x1ffb, 0x12);
+
+        cpu.clock();
+        cpu.clock();
+        assert!(cpu.flags.i());
+
+        cpu.trigger_nmi();
+        cpu.trigger_nmi();
+        for _ in 0..7 {
+            cpu.clock();
+        }
+
+        assert_eq!(cpu.pc, 0x1200);
+        assert_eq!(cpu.cycles, 2 + 7);
+        assert_eq!(cpu.sp, STACK_INIT.wrapping_sub(3));
+        assert_eq!(cpu.bus.read(0x00ff), 0x10);
+        assert_eq!(cpu.bus.read(0x00fe), 0x01);
+        // I set, B clear, unused bit set
+        assert_eq!(cpu.bus.read(0x00fd), 0x24);
+        assert!(cpu.flags.i());
+
+        // Only a single NMI was taken for both edges
+        cpu.bus.write(0x1200, 0xEA);
+        cpu.clock();
+        cpu.clock();
+        assert_eq!(cpu.pc, 0x1201);
+    }
+
+    #[test]
+    fn nmi_takes_priority_over_irq() {
+        #[rustfmt::skip]
+        let program = [
+            0x58, // 1000: CLI
+            0xEA, // 1001: NOP
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+        cpu.bus.write(0x1ffa, 0x00);
+        cpu.bus.write(0x1ffb, 0x12);
+        cpu.bus.write(0x1ffe, 0x00);
+        cpu.bus.write(0x1fff, 0x13);
+        cpu.bus.write(0x1200, 0x58); // 1200: CLI
+        cpu.bus.write(0x1201, 0xEA); // 1201: NOP
+        cpu.bus.write(0x1300, 0xEA); // 1300: NOP
+
+        cpu.clock();
+        cpu.clock();
+
+        cpu.set_irq(true);
+        cpu.trigger_nmi();
+        for _ in 0..7 {
+            cpu.clock();
+        }
+        assert_eq!(cpu.pc, 0x1200);
+
+        // The IRQ is still held, so it is taken as soon as the NMI handler re-enables interrupts
+        cpu.clock();
+        cpu.clock();
+        assert_eq!(cpu.pc, 0x1201);
+        for _ in 0..7 {
+            cpu.clock();
+        }
+        assert_eq!(cpu.pc, 0x1300);
+        assert!(cpu.flags.i());
+    }
+
+    #[test]
+    fn irq_is_masked_by_interrupt_disable() {
+        #[rustfmt::skip]
+        let program = [
+            0x78, // 1000: SEI
+            0xEA, // 1001: NOP
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+
+        cpu.clock();
+        cpu.clock();
+        cpu.set_irq(true);
+        cpu.clock();
+        cpu.clock();
+
+        assert_eq!(cpu.pc, 0x1002);
+    }
+
+    #[test]
+    fn nmi_hijacks_brk() {
+        #[rustfmt::skip]
+        let program = [
+            0x00, 0x00, // 1000: BRK
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+        cpu.bus.write(0x1ffa, 0x00);
+        cpu.bus.write(0x1ffb, 0x12);
+        cpu.bus.write(0x1ffe, 0x00);
+        cpu.bus.write(0x1fff, 0x13);
+        cpu.bus.write(0x1200, 0xEA);
+
+        // The NMI edge arrives in the middle of BRK
+        cpu.clock();
+        cpu.trigger_nmi();
+        for _ in 0..6 {
+            cpu.clock();
+        }
+
+        assert_eq!(cpu.pc, 0x1200);
+        // B set in the pushed flags, and the return address skips BRK's padding byte
+        assert_eq!(cpu.bus.read(0x00fd) & 0x10, 0x10);
+        assert_eq!(cpu.bus.read(0x00fe), 0x02);
+
+        // The NMI was consumed by BRK and isn't taken a second time
+        cpu.clock();
+        cpu.clock();
+        assert_eq!(cpu.pc, 0x1201);
+    }
+
+    #[test]
+    fn every_opcode_has_a_handler() {
+        for (opcode, Opcode(inst, ..)) in OPCODES.iter().enumerate() {
+            if let Instruction::None = inst {
+                continue;
+            }
+
+            let mut cpu = cpu_with_program(0x1000, &[opcode as u8, 0x00, 0x00]);
+            for _ in 0..8 {
+                cpu.clock();
+                if cpu.instructions_executed() == 1 {
+                    break;
+                }
+            }
+            assert_eq!(cpu.instructions_executed(), 1, "opcode {:02X}", opcode);
+        }
+    }
+
+    #[test]
+    fn indirect_jmp_wraps_within_page() {
+        #[rustfmt::skip]
+        let program = [
+            0x6C, 0xFF, 0x11, // 1000: JMP ($11FF)
+        ];
+
+        for (cmos, target) in [(false, 0x1200), (true, 0x1300)] {
+            let mut cpu = cpu_with_program(0x1000, &program);
+            cpu.set_cmos_jmp(cmos);
+            cpu.bus.write(0x11FF, 0x00);
+            cpu.bus.write(0x1100, 0x12);
+            cpu.bus.write(0x1200, 0x13);
+
+            for _ in 0..5 {
+                cpu.clock();
+            }
+            assert_eq!(cpu.pc, target, "cmos: {}", cmos);
+            assert_eq!(cpu.instructions_executed(), 1);
+        }
+    }
+
+    #[test]
+    fn zero_page_pointers_wrap_with_cmos_jmp() {
+        #[rustfmt::skip]
+        let program = [
+            0xB1, 0xFF, // 1000: LDA ($FF),Y
+            0xA1, 0xFF, // 1002: LDA ($FF,X)
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+        cpu.set_cmos_jmp(true);
+        cpu.bus.write(0x00FF, 0x00);
+        cpu.bus.write(0x0000, 0x12);
+        cpu.bus.write(0x0100, 0x13);
+        cpu.bus.write(0x1200, 0x42);
+        cpu.bus.write(0x1300, 0x17);
+
+        for _ in 0..5 {
+            cpu.clock();
+        }
+        assert_eq!(cpu.a, 0x42);
+
+        cpu.a = 0;
+        for _ in 0..6 {
+            cpu.clock();
+        }
+        assert_eq!(cpu.a, 0x42);
+        assert_eq!(cpu.instructions_executed(), 2);
+    }
+
+    #[test]
+    fn isb_subtracts_in_decimal_mode() {
+        // Accumulator result of SBC in decimal mode, from sequence 3 in the appendix of
+        // http://www.6502.org/tutorials/decimal_mode.html
+        fn reference(a: u8, b: u8, c: bool) -> u8 {
+            let mut al = (a & 0x0f) as i16 - (b & 0x0f) as i16 + c as i16 - 1;
+            if al < 0 {
+                al = ((al - 0x06) & 0x0f) - 0x10;
+            }
+            let mut a = (a & 0xf0) as i16 - (b & 0xf0) as i16 + al;
+            if a < 0 {
+                a -= 0x60;
+            }
+            a as u8
+        }
+
+        let bcd = (0..100u8).map(|n| ((n / 10) << 4) | (n % 10));
+        for a in bcd.clone() {
+            for b in bcd.clone() {
+                for c in [false, true] {
+                    let mut cpu = cpu_with_program(0x1000, &[]);
+                    cpu.bus.write(0x0080, b.wrapping_sub(1));
+                    cpu.a = a;
+                    cpu.flags.set_d(true);
+                    cpu.flags.set_c(c);
+
+                    cpu.isb(0x0080);
+
+                    assert_eq!(cpu.bus.read(0x0080), b);
+                    assert_eq!(cpu.a, reference(a, b, c), "{:02X} - {:02X} - {}", a, b, !c);
+                    assert_eq!(cpu.flags.c(), a as i16 - b as i16 - !c as i16 >= 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rra_adds_like_adc() {
+        for decimal in [false, true] {
+            for a in 0..=255u8 {
+                for val in (0..=255u8).step_by(3) {
+                    for c in [false, true] {
+                        let mut adc = cpu_with_program(0x1000, &[]);
+                        adc.bus.write(0x0080, val);
+                        adc.a = a;
+                        adc.flags.set_d(decimal);
+                        adc.flags.set_c(c);
+                        adc.adc(0x0080);
+
+                        // Rotating right puts the old carry into bit 7 and bit 0 into the carry
+                        let mut rra = cpu_with_program(0x1000, &[]);
+                        rra.bus.write(0x0080, (val << 1) | c as u8);
+                        rra.a = a;
+                        rra.flags.set_d(decimal);
+                        rra.flags.set_c(val & 0x80 != 0);
+                        rra.rra(0x0080, AddressingMode::ZeroPageIndexed);
+
+                        assert_eq!(rra.bus.read(0x0080), val);
+                        assert_eq!(
+                            (rra.a, rra.flags()),
+                            (adc.a, adc.flags()),
+                            "{:02X} + {:02X} + {}, decimal: {}",
+                            a,
+                            val,
+                            c,
+                            decimal
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn restore_finishes_instruction_in_progress() {
+        #[rustfmt::skip]
+        let program = [
+            0xAD, 0x00, 0x11, // 1000: LDA $1100      4
+            0xE8,             // 1003: INX            2
+        ];
+        let mut cpu = cpu_with_program(0x1000, &program);
+        cpu.bus.write(0x1100, 0x42);
+
+        // Half way through the LDA
+        cpu.clock();
+        cpu.clock();
+        let snapshot = cpu.snapshot();
+
+        let mut expected = cpu_with_program(0x1000, &program);
+        expected.bus.write(0x1100, 0x42);
+        for _ in 0..4 + 2 {
+            expected.clock();
+        }
+
+        for _ in 0..4 + 2 {
+            cpu.clock();
+        }
+        cpu.a = 0;
+        cpu.x = 0x80;
+        cpu.set_flags(0xff);
+        cpu.pc = 0x1234;
+
+        cpu.restore(snapshot);
+        assert_eq!(cpu.a, 0);
+        for _ in 0..2 + 2 {
+            cpu.clock();
+        }
+
+        assert_eq!(cpu.a, expected.a);
+        assert_eq!(cpu.x, expected.x);
+        assert_eq!(cpu.flags(), expected.flags());
+        assert_eq!(cpu.pc, expected.pc);
+        assert_eq!(cpu.total_cycles(), expected.total_cycles());
+        assert_eq!(cpu.instructions_executed(), 2);
+    }
+
+    #[test]
+    fn flat_bus_sees_all_address_lines() {
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x42,       // 0400: LDA #$42
+            0x8D, 0x00, 0x20, // 0402: STA $2000
+            0xAD, 0x00, 0x00, // 0405: LDA $0000
+        ];
+        let mut cpu = CPU6507::new(FlatBus::with_program(0x0400, &program));
+        cpu.reset();
+        for _ in 0..2 + 4 + 4 {
+            cpu.clock();
+        }
+
+        // On the 6507 $2000 would be a mirror of $0000
+        assert_eq!(cpu.bus.mem()[0x2000], 0x42);
+        assert_eq!(cpu.a, 0x00);
+    }
+
+    // Klaus Dormann's 6502 functional test (https://github.com/Klaus2m5/6502_65C02_functional_tests),
+    // assembled with the default options: a 64K image that starts at $0400 and traps in a jump to
+    // itself, at $3469 once every test has passed. It isn't distributed with the crate, the test is
+    // skipped unless the binary has been put in place.
+    const FUNCTIONAL_TEST: &str = "tests/roms/6502_functional_test.bin";
+    const FUNCTIONAL_TEST_START: u16 = 0x0400;
+    const FUNCTIONAL_TEST_SUCCESS: u16 = 0x3469;
+
+    #[test]
+    fn klaus_dormann_functional_test() {
+        let Ok(mem) = std::fs::read(FUNCTIONAL_TEST) else {
+            eprintln!("skipping: {} not found", FUNCTIONAL_TEST);
+            return;
+        };
+        let mut cpu = CPU6507::new(FlatBus::from_image(&mem));
+        cpu.reset();
+        cpu.pc = FUNCTIONAL_TEST_START;
+
+        // A full pass takes around 30 million instructions
+        for _ in 0..100_000_000 {
+            let pc = cpu.pc;
+            let instructions = cpu.instructions_executed();
+            while cpu.instructions_executed() == instructions {
+                cpu.clock();
+            }
+
+            if cpu.pc == pc {
+                assert_eq!(
+                    pc, FUNCTIONAL_TEST_SUCCESS,
+                    "trapped at {:04X}, see the test's listing for the failing test",
+                    pc
+                );
+                return;
+            }
+        }
+        panic!("no trap reached, PC: {:04X}", cpu.pc);
     }
 }
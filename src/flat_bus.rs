@@ -0,0 +1,71 @@
+// A plain 6502 system with 64K of RAM and nothing else on the bus, the way standard CPU test
+// programs expect to run. It lets the CPU be tested apart from the 2600's memory map.
+use crate::bus::Bus;
+use alloc::boxed::Box;
+
+const MEMORY_SIZE: usize = 0x10000;
+const RESET_VECTOR: usize = 0xfffc;
+
+pub(crate) struct FlatBus {
+    mem: Box<[u8; MEMORY_SIZE]>,
+}
+
+impl FlatBus {
+    // Loads `program` at `origin`, and points the reset vector at it
+    pub fn with_program(origin: u16, program: &[u8]) -> Self {
+        let mut mem = Box::new([0; MEMORY_SIZE]);
+        let start = origin as usize;
+        mem[start..start + program.len()].copy_from_slice(program);
+        mem[RESET_VECTOR..RESET_VECTOR + 2].copy_from_slice(&origin.to_le_bytes());
+        Self { mem }
+    }
+
+    // Takes a whole 64K memory image, such as a test program assembled to fill the address space
+    pub fn from_image(image: &[u8]) -> Self {
+        let mem = Box::new(
+            image
+                .try_into()
+                .unwrap_or_else(|_| panic!("expected a 64K image, got {} bytes", image.len())),
+        );
+        Self { mem }
+    }
+
+    pub fn mem(&self) -> &[u8; MEMORY_SIZE] {
+        &self.mem
+    }
+}
+
+impl Bus for FlatBus {
+    const ADDRESS_MASK: u16 = 0xffff;
+
+    fn read(&mut self, address: u16) -> u8 {
+        self.mem[address as usize]
+    }
+
+    fn write(&mut self, address: u16, val: u8) {
+        self.mem[address as usize] = val;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_programs_and_images() {
+        let mut bus = FlatBus::with_program(0x0400, &[0xa9, 0x42]);
+        assert_eq!(bus.read(0x0401), 0x42);
+        assert_eq!([bus.read(0xfffc), bus.read(0xfffd)], [0x00, 0x04]);
+
+        let mut image = vec![0; MEMORY_SIZE];
+        image[0xffff] = 0x17;
+        let mut bus = FlatBus::from_image(&image);
+        assert_eq!(bus.read(0xffff), 0x17);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a 64K image")]
+    fn short_image_is_rejected() {
+        FlatBus::from_image(&[0; 0x1000]);
+    }
+}
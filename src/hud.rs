@@ -0,0 +1,65 @@
+use image::Rgba;
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+// Glyph width plus one column of spacing
+const CHAR_WIDTH: usize = GLYPH_WIDTH + 1;
+// Glyph height plus one row of spacing
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+const FOREGROUND: Rgba<u8> = Rgba([0xff, 0xff, 0xff, 0xff]);
+const BACKGROUND: Rgba<u8> = Rgba([0x00, 0x00, 0x00, 0xff]);
+
+// A tiny 3x5 bitmap font. Each row is 3 bits wide, most significant bit on the left.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+// Draws each line of text onto the top rows of the frame, on a black background so that it stays
+// readable whatever the game is drawing underneath.
+pub(crate) fn draw(frame: &mut [[Rgba<u8>; 160]; 192], lines: &[String]) {
+    for (i, line) in lines.iter().enumerate() {
+        let top = i * LINE_HEIGHT;
+
+        for (j, c) in line.chars().enumerate() {
+            let left = j * CHAR_WIDTH;
+            if left + CHAR_WIDTH > frame[0].len() || top + LINE_HEIGHT > frame.len() {
+                break;
+            }
+
+            let rows = glyph(c);
+            for (y, row) in frame[top..top + LINE_HEIGHT].iter_mut().enumerate() {
+                for (x, pixel) in row[left..left + CHAR_WIDTH].iter_mut().enumerate() {
+                    let lit = y < GLYPH_HEIGHT
+                        && x < GLYPH_WIDTH
+                        && (rows[y] >> (GLYPH_WIDTH - 1 - x)) & 1 != 0;
+                    *pixel = if lit { FOREGROUND } else { BACKGROUND };
+                }
+            }
+        }
+    }
+}
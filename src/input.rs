@@ -0,0 +1,168 @@
+use alloc::{boxed::Box, format, vec::Vec};
+use core::{error::Error, fmt, str::FromStr};
+
+/// One `KeyEvent` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Up(bool),
+    Down(bool),
+    Left(bool),
+    Right(bool),
+    Select(bool),
+    Reset(bool),
+    JoystickFire(bool),
+    Color,
+}
+
+/// An input recorded by `EmulatorCore::start_recording`. `frame` counts the frames run since the
+/// recording started, the input was made before that frame ran.
+///
+/// Events convert to and from a line of text, such as `12 fire 1`, so a recording can be stored
+/// and loaded without any extra dependencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub kind: InputKind,
+}
+
+impl fmt::Display for InputKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, pressed) = match *self {
+            InputKind::Up(p) => ("up", p),
+            InputKind::Down(p) => ("down", p),
+            InputKind::Left(p) => ("left", p),
+            InputKind::Right(p) => ("right", p),
+            InputKind::Select(p) => ("select", p),
+            InputKind::Reset(p) => ("reset", p),
+            InputKind::JoystickFire(p) => ("fire", p),
+            InputKind::Color => return write!(f, "color"),
+        };
+        write!(f, "{} {}", name, pressed as u8)
+    }
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.frame, self.kind)
+    }
+}
+
+impl FromStr for InputEvent {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let frame = fields
+            .next()
+            .ok_or("empty input event")?
+            .parse::<u64>()
+            .map_err(|e| format!("invalid frame in input event '{}': {}", s, e))?;
+        let name = fields
+            .next()
+            .ok_or_else(|| format!("missing input in input event '{}'", s))?;
+
+        let kind = if name == "color" {
+            InputKind::Color
+        } else {
+            let pressed = match fields.next() {
+                Some("0") => false,
+                Some("1") => true,
+                _ => return Err(format!("invalid button state in input event '{}'", s).into()),
+            };
+            match name {
+                "up" => InputKind::Up(pressed),
+                "down" => InputKind::Down(pressed),
+                "left" => InputKind::Left(pressed),
+                "right" => InputKind::Right(pressed),
+                "select" => InputKind::Select(pressed),
+                "reset" => InputKind::Reset(pressed),
+                "fire" => InputKind::JoystickFire(pressed),
+                _ => return Err(format!("unknown input in input event '{}'", s).into()),
+            }
+        };
+
+        if fields.next().is_some() {
+            return Err(format!("trailing data in input event '{}'", s).into());
+        }
+        Ok(InputEvent { frame, kind })
+    }
+}
+
+// Collects the inputs made while recording, numbered by the frame they were made before
+#[derive(Default)]
+pub(crate) struct InputRecorder {
+    frame: u64,
+    events: Vec<InputEvent>,
+}
+
+impl InputRecorder {
+    pub fn record(&mut self, kind: InputKind) {
+        self.events.push(InputEvent {
+            frame: self.frame,
+            kind,
+        });
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // Follows the console from the end of frame `from` to the end of frame `to`, when a save state
+    // is loaded. Going back, the inputs made since `to` were for frames that will run again, so
+    // they're dropped.
+    pub fn jump(&mut self, from: u64, to: u64) {
+        if to < from {
+            self.frame = self.frame.saturating_sub(from - to);
+            let frame = self.frame;
+            self.events.retain(|event| event.frame < frame);
+        } else {
+            self.frame += to - from;
+        }
+    }
+
+    // Forgets the inputs made since the current frame started, when it's rewound to its start
+    pub fn drop_current_frame(&mut self) {
+        let frame = self.frame;
+        self.events.retain(|event| event.frame < frame);
+    }
+
+    pub fn into_events(self) -> Vec<InputEvent> {
+        self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn events_round_trip_through_text() {
+        let events = [
+            InputEvent {
+                frame: 0,
+                kind: InputKind::Reset(true),
+            },
+            InputEvent {
+                frame: 12,
+                kind: InputKind::JoystickFire(false),
+            },
+            InputEvent {
+                frame: 345,
+                kind: InputKind::Color,
+            },
+        ];
+
+        for event in events {
+            assert_eq!(event.to_string().parse::<InputEvent>().unwrap(), event);
+        }
+        assert_eq!(events[1].to_string(), "12 fire 0");
+    }
+
+    #[test]
+    fn malformed_events_are_errors() {
+        for line in ["", "x up 1", "3", "3 up", "3 up 2", "3 jump 1", "3 color 1"] {
+            assert!(line.parse::<InputEvent>().is_err(), "'{}'", line);
+        }
+    }
+}
@@ -1,94 +1,1254 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(test)]
+mod asm;
 mod bus;
+mod controller;
 mod cpu6507;
+#[cfg(test)]
+mod flat_bus;
+#[cfg(feature = "image")]
+mod hud;
+mod input;
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) mod memory;
 mod opcode;
+#[cfg(feature = "encode")]
+mod recorder;
+mod region;
 mod riot;
+pub mod tables;
 mod tia;
+mod trace;
 
-use crate::{bus::AtariBus, cpu6507::CPU6507, riot::RIOT, tia::TIA};
-use image::Rgba;
-use log::info;
-use std::{cell::RefCell, error::Error, fs::File, io::Read, rc::Rc};
+use crate::{
+    bus::{AtariBus, Bus, Diagnostics},
+    cpu6507::CPU6507,
+    input::InputRecorder,
+    riot::RIOT,
+    tia::TIA,
+};
+use alloc::{boxed::Box, collections::VecDeque, format, vec, vec::Vec};
+pub use bus::{RegisterWarning, RegisterWarningKind};
+pub use controller::{Controller, Direction, GenesisButton};
+use core::error::Error;
+pub use cpu6507::CpuSnapshot;
+#[cfg(feature = "image")]
+use image::{ImageError, ImageFormat, Rgb, RgbImage, Rgba, RgbaImage};
+pub use input::{InputEvent, InputKind};
+use log::{info, warn};
+pub use memory::{MemoryRegion, PiaAddress, TiaReadAddress, TiaWriteAddress};
+#[cfg(feature = "encode")]
+pub use recorder::FrameRecorder;
+pub use region::Region;
+pub use riot::{RamInit, TimerState};
+#[cfg(feature = "std")]
+use std::{fs::File, io::Read, time::Instant};
+pub use tia::{
+    Audio, AudioChannel, AudioDiff, ChannelDiff, ColorRegisters, SyncKind, TiaObjectId, TiaSnapshot,
+};
+pub use trace::TraceEntry;
 
-type SharedRIOT = Rc<RefCell<RIOT>>;
-type SharedTIA = Rc<RefCell<TIA>>;
 // type SharedDebugger = Rc<RefCell<Debugger>>;
 
 const CLOCKS_PER_SCANLINE: usize = 228;
+const H_BLANK_CLOCKS: usize = 68;
+// The TIA's horizontal counter powers on at the start of a line, and steps to the next color
+// clock before drawing it, so the first one it draws is clock 1
+const POWER_ON_COLOR_CLOCK: usize = 1;
+// The color clock within each CPU cycle that the CPU is clocked on, see `handle_cpu_clock`
+const CPU_PHASE: usize = 2;
+
+// Title frame detection: the minimum number of distinct colors a frame needs to be considered
+// content-rich, the maximum share of pixels (1/N) allowed to change between frames for it to be
+// considered stable, and how many stable frames in a row are required.
+const TITLE_MIN_COLORS: usize = 3;
+const TITLE_MAX_CHANGE_DIVISOR: usize = 100;
+const TITLE_STABLE_FRAMES: usize = 3;
+
+// The cartridge address space is 4K. Smaller 2K cartridges are mirrored into it by the bus.
+const CARTRIDGE_SIZE: usize = 4096;
+const HALF_CARTRIDGE_SIZE: usize = 2048;
 
+// FNV-1a parameters for `frame_hash`
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// Number of recent frames `measured_fps` averages over
+#[cfg(feature = "std")]
+const FPS_WINDOW: usize = 60;
+
+/// The emulated console. It owns all of its state, with no locks or reference counting inside, so
+/// it is `Send`: a front end can run it on a worker thread and keep its UI thread free. Callbacks
+/// have to be `Send` for the same reason. The frame buffers make it large, so box it before moving
+/// it to a thread, whose stack may be too small to hold it in a debug build.
 pub struct EmulatorCore {
-    cpu: CPU6507,
-    tia: SharedTIA,
-    riot: SharedRIOT,
+    // The TIA and RIOT are owned by the CPU's bus, see `tia()` and `riot()`
+    cpu: CPU6507<AtariBus>,
+    // Color codes (0-127) of the last frame, see `frame_indices`
+    frame_indices: [[u8; 160]; 192],
+    #[cfg(feature = "image")]
     frame_pixels: [[Rgba<u8>; 160]; 192],
+    // The lines above and below the picture `full_frame` includes, see `set_extra_lines`
+    extra_lines: (usize, usize),
+    // The picture and the extra lines around it, empty if there are none
+    full_frame: Vec<[u8; 160]>,
+    // Number of scanlines run so far in the current frame
+    scanline: usize,
+    // Color clock within the current scanline
+    color_clock: usize,
+    #[cfg(feature = "image")]
+    debug_hud: bool,
+    region: Region,
+    // Number of frames `run` has completed since power on
+    frame_count: u64,
+    // Number of scanlines the last frame `run` took, from VSYNC to VSYNC
+    last_frame_scanlines: Option<usize>,
+    // Lines without a WSYNC write since the current frame started
+    lines_without_wsync: usize,
+    // The TIA registers written during the last frame, one bit per write address
+    tia_registers_touched: u64,
+    // The inputs made since `start_recording`, if recording
+    recording: Option<InputRecorder>,
+    // The state at the end of each of the most recent frames, oldest first, see `rewind`
+    rewind_history: VecDeque<SaveState>,
+    // Number of frames kept in `rewind_history`, 0 if rewinding is off
+    rewind_length: usize,
+    // Callbacks are `Send` so that the core is, see `EmulatorCore`
+    sync_callback: Option<Box<dyn FnMut(SyncEvent) + Send>>,
+    diagnostics_callback: Option<Box<dyn FnMut(RegisterWarning) + Send>>,
+    // When the most recent frames finished running, oldest first
+    #[cfg(feature = "std")]
+    frame_times: VecDeque<Instant>,
+}
+
+/// Loads `rom`, runs it for `frames` frames and returns the hash of the last one, e.g. to check in
+/// CI that a ROM boots and renders deterministically.
+pub fn smoke_test(rom: &[u8], frames: usize) -> Result<u64, Box<dyn Error>> {
+    let mut emulator = init_emulator_from_bytes(rom.to_vec())?;
+    for _ in 0..frames {
+        emulator.run();
+    }
+    Ok(emulator.frame_hash())
+}
+
+/// The state of the whole console at one point in time, taken with `EmulatorCore::save_state`. It
+/// includes the cartridge ROM, but not the frame buffers.
+#[derive(Clone)]
+pub struct SaveState {
+    cpu: CPU6507<AtariBus>,
+    scanline: usize,
+    color_clock: usize,
+    frame_count: u64,
+    last_frame_scanlines: Option<usize>,
+    lines_without_wsync: usize,
+    tia_registers_touched: u64,
+}
+
+/// How the scanlines of a frame were spent, returned by `EmulatorCore::run_frame_stats`. A
+/// standard NTSC frame has 3 lines of VSYNC, 37 of VBLANK, 192 visible lines and 30 of overscan,
+/// 262 in all. A PAL frame has 3, 45, 228 and 36, 312 in all. A frame that doesn't add up to the
+/// usual total will make the picture roll on a real TV.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Lines in the frame, the sum of the four kinds below.
+    pub scanlines: usize,
+    /// Lines with VSYNC on.
+    pub vsync_lines: usize,
+    /// Lines after VSYNC, before the first visible one.
+    pub vblank_lines: usize,
+    /// Lines with VBLANK off, the picture.
+    pub visible_lines: usize,
+    /// Lines after the first visible one with VBLANK on, until VSYNC.
+    pub overscan_lines: usize,
+    /// Lines in which the ROM didn't write WSYNC. Kernels normally write it on every line.
+    pub lines_without_wsync: usize,
+}
+
+impl FrameStats {
+    /// Whether VSYNC was held for the 3 lines the TV expects.
+    pub fn vsync_well_formed(&self) -> bool {
+        self.vsync_lines == 3
+    }
+}
+
+/// An endless iterator over frames, returned by `EmulatorCore::frames`. Each item is a copy of
+/// `frame_indices` after running a frame, since an `Iterator` can't hand out a borrow of a buffer
+/// that the next frame overwrites. To skip the copy, call `run` and read `frame_indices` instead.
+pub struct Frames<'a> {
+    emulator: &'a mut EmulatorCore,
+}
+
+impl Iterator for Frames<'_> {
+    type Item = [[u8; 160]; 192];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.emulator.run();
+        Some(self.emulator.frame_indices)
+    }
+}
+
+/// A change to VSYNC or VBLANK, passed to the callback set with `EmulatorCore::set_sync_callback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncEvent {
+    /// Which register changed, and how.
+    pub kind: SyncKind,
+    /// The scanline of the frame `run` was running, as returned by `beam_position`.
+    pub scanline: usize,
+    /// The number of frames completed before, see `EmulatorCore::frame_count`.
+    pub frame: u64,
+}
+
+/// Settings for a new console, passed to `init_emulator_with`. Start from `new()` (the defaults)
+/// and chain the setters:
+///
+/// ```
+/// use atari2600_lib::{EmulatorOptions, RamInit, Region};
+///
+/// let options = EmulatorOptions::new()
+///     .region(Region::Pal)
+///     .ram_init(RamInit::Random(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct EmulatorOptions {
+    ram_init: RamInit,
+    region: Region,
+    difficulty_hard: [bool; 2],
+    color_tv: bool,
+    extra_lines: (usize, usize),
+}
+
+impl Default for EmulatorOptions {
+    fn default() -> Self {
+        Self {
+            ram_init: RamInit::Zeroed,
+            region: Region::Ntsc,
+            difficulty_hard: [true, true],
+            color_tv: true,
+            extra_lines: (0, 0),
+        }
+    }
+}
+
+impl EmulatorOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How RAM is filled at power on. Defaults to `RamInit::Zeroed`.
+    pub fn ram_init(mut self, ram_init: RamInit) -> Self {
+        self.ram_init = ram_init;
+        self
+    }
+
+    /// The TV standard, which sets the frame timing. Defaults to `Region::Ntsc`.
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// The starting position of player 0's (0) or player 1's (1) difficulty switch. `hard` is
+    /// the "A" (pro) position. Both default to hard.
+    ///
+    /// Panics if `player` is greater than 1.
+    pub fn difficulty(mut self, player: u8, hard: bool) -> Self {
+        assert!(player < 2, "difficulty switch {} out of range", player);
+        self.difficulty_hard[player as usize] = hard;
+        self
+    }
+
+    /// The starting position of the TV type switch, color or black and white. Defaults to color.
+    pub fn color_tv(mut self, color: bool) -> Self {
+        self.color_tv = color;
+        self
+    }
+
+    /// The number of lines above and below the 192 line picture to include in
+    /// `EmulatorCore::full_frame_indices`. Defaults to none.
+    pub fn extra_lines(mut self, top: usize, bottom: usize) -> Self {
+        self.extra_lines = (top, bottom);
+        self
+    }
 }
 
+#[cfg(feature = "std")]
 pub fn init_emulator<P: AsRef<str>>(rom_path: P) -> Result<EmulatorCore, Box<dyn Error>> {
-    let (riot, tia, cpu) = initialize_components(rom_path)?;
-    let frame_pixels = [[Rgba::<u8>([0, 0, 0, 0xff]); 160]; 192];
-    Ok(EmulatorCore {
+    init_emulator_with(rom_path, EmulatorOptions::default())
+}
+
+#[cfg(feature = "std")]
+pub fn init_emulator_with<P: AsRef<str>>(
+    rom_path: P,
+    options: EmulatorOptions,
+) -> Result<EmulatorCore, Box<dyn Error>> {
+    let rom_path = rom_path.as_ref();
+    let mut fh =
+        File::open(rom_path).map_err(|e| format!("unable to open rom {}: {}", rom_path, e))?;
+
+    let mut rom = vec![];
+    let bytes = fh
+        .read_to_end(&mut rom)
+        .map_err(|e| format!("unable to read rom {}: {}", rom_path, e))?;
+    info!("ROM: {} ({} bytes)", rom_path, bytes);
+
+    init_emulator_from_bytes_with(rom, options)
+}
+
+/// Like `init_emulator`, but takes the ROM image itself rather than a path to it, e.g. for a ROM
+/// embedded in the binary or fetched by a web front end. This is the only way to load a ROM without
+/// the `std` feature.
+pub fn init_emulator_from_bytes(rom: Vec<u8>) -> Result<EmulatorCore, Box<dyn Error>> {
+    init_emulator_from_bytes_with(rom, EmulatorOptions::default())
+}
+
+pub fn init_emulator_from_bytes_with(
+    rom: Vec<u8>,
+    options: EmulatorOptions,
+) -> Result<EmulatorCore, Box<dyn Error>> {
+    let cpu = initialize_components(rom, &options)?;
+    let mut emulator = EmulatorCore {
         cpu,
-        tia,
-        riot,
-        frame_pixels,
-    })
+        frame_indices: [[0; 160]; 192],
+        #[cfg(feature = "image")]
+        frame_pixels: [[Rgba::<u8>([0, 0, 0, 0xff]); 160]; 192],
+        extra_lines: (0, 0),
+        full_frame: Vec::new(),
+        scanline: 0,
+        color_clock: POWER_ON_COLOR_CLOCK,
+        #[cfg(feature = "image")]
+        debug_hud: false,
+        region: options.region,
+        frame_count: 0,
+        last_frame_scanlines: None,
+        lines_without_wsync: 0,
+        tia_registers_touched: 0,
+        recording: None,
+        rewind_history: VecDeque::new(),
+        rewind_length: 0,
+        sync_callback: None,
+        diagnostics_callback: None,
+        #[cfg(feature = "std")]
+        frame_times: VecDeque::with_capacity(FPS_WINDOW),
+    };
+    emulator.set_extra_lines(options.extra_lines.0, options.extra_lines.1);
+    Ok(emulator)
 }
 
 impl EmulatorCore {
+    /// Returns the last frame as TIA color codes (0-127), i.e. the color register value shifted
+    /// right by one, for front ends that do the palette lookup themselves.
+    pub fn frame_indices(&self) -> &[[u8; 160]; 192] {
+        &self.frame_indices
+    }
+
+    #[cfg(feature = "image")]
     pub fn frame_pixels(&self) -> &[[Rgba<u8>; 160]; 192] {
         &self.frame_pixels
     }
 
+    /// Like `frame_indices`, with the lines set by `set_extra_lines` above and below the picture.
+    /// Rows are consecutive scanlines, starting `top` lines before the first one with VBLANK off.
+    /// VBLANK isn't applied to them, so they show what the ROM draws outside the picture. Rows a
+    /// frame doesn't reach are left at 0.
+    pub fn full_frame_indices(&self) -> &[[u8; 160]] {
+        if self.full_frame.is_empty() {
+            &self.frame_indices
+        } else {
+            &self.full_frame
+        }
+    }
+
+    /// Includes `top` lines of VBLANK and `bottom` lines of overscan around the picture in
+    /// `full_frame_indices`, e.g. to see a kernel that draws more than 192 lines. They take
+    /// effect from the next frame.
+    pub fn set_extra_lines(&mut self, top: usize, bottom: usize) {
+        self.extra_lines = (top, bottom);
+        self.full_frame = if top == 0 && bottom == 0 {
+            Vec::new()
+        } else {
+            vec![[0; 160]; top + 192 + bottom]
+        };
+    }
+
+    /// A hash of the last frame's color codes, `frame_indices`. It's the same on every platform,
+    /// Rust version and set of features, so it can be stored and compared against later. It
+    /// hashes the color codes rather than the RGB bytes, since those only exist with the `image`
+    /// feature. Every color code maps to one RGB color, so apart from the debug HUD, which it
+    /// leaves out, it changes exactly when `frame_rgb24` does, and that can be dumped when a hash
+    /// doesn't match.
+    pub fn frame_hash(&self) -> u64 {
+        self.frame_indices
+            .iter()
+            .flatten()
+            .fold(FNV_OFFSET_BASIS, |hash, &index| {
+                (hash ^ index as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// Returns the last frame as packed RGB bytes, row by row, e.g. to write it out as a PPM or
+    /// PNG file.
+    #[cfg(feature = "image")]
+    pub fn frame_rgb24(&self) -> Vec<u8> {
+        self.frame_pixels
+            .iter()
+            .flatten()
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect()
+    }
+
+    /// Copies the current frame into an owned `RgbImage`, dropping the alpha channel.
+    #[cfg(feature = "image")]
+    pub fn to_rgb_image(&self) -> RgbImage {
+        RgbImage::from_fn(160, 192, |x, y| {
+            let [r, g, b, _] = self.frame_pixels[y as usize][x as usize].0;
+            Rgb([r, g, b])
+        })
+    }
+
+    /// Writes the last frame to a PNG file at `path`, e.g. for a bug report.
+    #[cfg(feature = "image")]
+    pub fn save_frame_png(&self, path: &str) -> std::io::Result<()> {
+        RgbaImage::from_fn(160, 192, |x, y| self.frame_pixels[y as usize][x as usize])
+            .save_with_format(path, ImageFormat::Png)
+            .map_err(|e| match e {
+                ImageError::IoError(e) => e,
+                e => std::io::Error::other(e),
+            })
+    }
+
+    /// Returns the last values written to COLUP0, COLUP1, COLUPF and COLUBK.
+    pub fn colors(&self) -> ColorRegisters {
+        self.tia().colors()
+    }
+
+    /// Returns a copy of the TIA registers, object positions and collision latches. It has no side
+    /// effects, so it can be called at any point during a frame.
+    pub fn tia_state(&self) -> TiaSnapshot {
+        self.tia().snapshot()
+    }
+
+    /// Returns the horizontal position counter of one of the TIA's objects, 0-159. Unlike the
+    /// positions in `tia_state`, it counts single pixels: it steps once per visible pixel, and the
+    /// object is drawn a few pixels after it wraps around to 0. Read during HBLANK, an object at
+    /// `p` is drawn from column `(158 - p + 5) % 160` for players, or `(158 - p + 4) % 160` for
+    /// missiles and the ball, so e.g. a player at 100 is drawn from column 63.
+    pub fn object_position(&self, object: TiaObjectId) -> u8 {
+        self.tia().object_position(object)
+    }
+
+    /// Returns the RIOT timer state, without the side effects a CPU read of INTIM/INSTAT has.
+    pub fn timer_state(&self) -> TimerState {
+        self.riot().timer_state()
+    }
+
+    /// Lists the TIA registers the game wrote to during the last frame `run`, in address order. This
+    /// gives a quick picture of what a kernel drives.
+    pub fn tia_registers_touched_last_frame(&self) -> Vec<TiaWriteAddress> {
+        (0..=TiaWriteAddress::CXCLR as u16)
+            .filter(|&address| self.tia_registers_touched & (1 << address) != 0)
+            .filter_map(|address| address.try_into().ok())
+            .collect()
+    }
+
+    /// Returns a copy of the current audio state, e.g. to `diff` against a later one.
+    pub fn audio_snapshot(&self) -> Audio {
+        self.tia().audio().snapshot()
+    }
+
+    /// Returns whether the game has written to any AUDx register since the last call.
+    pub fn audio_registers_changed(&mut self) -> bool {
+        self.tia_mut().audio_mut().take_registers_changed()
+    }
+
+    /// Sets the difficulty switch of player 0 or 1. `hard` is the "A" (pro) position.
+    ///
+    /// Panics if `player` is greater than 1.
+    pub fn set_difficulty(&mut self, player: u8, hard: bool) {
+        self.riot_mut().set_difficulty(player, hard);
+    }
+
+    /// Sets the TV type switch to color, or black and white.
+    pub fn set_tv_type(&mut self, color: bool) {
+        self.riot_mut().set_tv_type(color);
+    }
+
+    /// Returns the number of accesses to invalid addresses made by the CPU so far.
+    pub fn invalid_accesses(&self) -> usize {
+        self.cpu.bus().diagnostics().invalid_accesses()
+    }
+
+    /// Sets how many invalid accesses to the same address get logged. Further accesses are
+    /// still counted by `invalid_accesses`.
+    pub fn set_invalid_access_log_limit(&mut self, limit: usize) {
+        self.cpu.bus_mut().diagnostics_mut().set_log_limit(limit);
+    }
+
+    /// Reads `addr` as the CPU would see it, but without side effects: reading the timer doesn't
+    /// clear the RIOT's interrupt flags, and invalid addresses read as 0 without being counted by
+    /// `invalid_accesses`. Reading the TIA never has side effects.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.cpu.bus().peek(addr & AtariBus::ADDRESS_MASK)
+    }
+
+    /// Reads `addr` exactly as the CPU would, side effects included.
+    pub fn peek_raw(&mut self, addr: u16) -> u8 {
+        self.cpu.read(addr)
+    }
+
+    /// Writes `val` to `addr` as the CPU would. Writes to the TIA and RIOT take effect as usual,
+    /// and writes to the cartridge change the loaded ROM.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.cpu.write(addr, val);
+    }
+
+    /// Describes what `address` maps to for the CPU, including which of its mirrors it is.
+    pub fn describe_address(&self, address: u16) -> MemoryRegion {
+        self.cpu.bus().describe(address)
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// The frame rate of the emulated console, derived from the region's master clock and frame
+    /// length. This is what the game runs at on real hardware, however fast it is emulated.
+    pub fn emulated_fps(&self) -> f64 {
+        let clocks_per_frame = CLOCKS_PER_SCANLINE * self.region.scanlines_per_frame();
+        self.region.master_clock() / clocks_per_frame as f64
+    }
+
+    /// The number of color clocks in a frame. Games don't always draw the number of scanlines their
+    /// region calls for, so this is measured from the last frame `run`, falling back to the
+    /// region's frame length before the first one.
+    pub fn clocks_per_frame(&self) -> usize {
+        let scanlines = self
+            .last_frame_scanlines
+            .unwrap_or_else(|| self.region.scanlines_per_frame());
+        CLOCKS_PER_SCANLINE * scanlines
+    }
+
+    /// The rate a host should call `run` at to play the game at its real speed: the region's master
+    /// clock over `clocks_per_frame`. Unlike `emulated_fps`, it follows the game's actual frame
+    /// length.
+    pub fn target_fps(&self) -> f64 {
+        self.region.master_clock() / self.clocks_per_frame() as f64
+    }
+
+    /// The number of frames per second `run` has actually been completing, averaged over the
+    /// last few frames. Returns `None` until at least two frames have been run.
+    #[cfg(feature = "std")]
+    pub fn measured_fps(&self) -> Option<f64> {
+        let first = self.frame_times.front()?;
+        let last = self.frame_times.back()?;
+        let elapsed = last.duration_since(*first).as_secs_f64();
+
+        if self.frame_times.len() < 2 || elapsed == 0.0 {
+            return None;
+        }
+        Some((self.frame_times.len() - 1) as f64 / elapsed)
+    }
+
+    /// Turns the console off and on again. The console switches, such as a held reset button, and
+    /// the controllers keep their state, since they are physical.
+    pub fn power_cycle(&mut self) {
+        self.riot_mut().power_cycle();
+        self.tia_mut().power_cycle();
+        self.cpu.bus_mut().update_controllers();
+        self.cpu.reset();
+
+        self.frame_indices = [[0; 160]; 192];
+        #[cfg(feature = "image")]
+        {
+            self.frame_pixels = [[Rgba::<u8>([0, 0, 0, 0xff]); 160]; 192];
+        }
+        self.scanline = 0;
+        self.color_clock = POWER_ON_COLOR_CLOCK;
+        self.frame_count = 0;
+        self.last_frame_scanlines = None;
+        self.tia_registers_touched = 0;
+        self.rewind_history.clear();
+        #[cfg(feature = "std")]
+        self.frame_times.clear();
+    }
+
+    /// Takes a snapshot of the console, which `load_state` can later restore.
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            cpu: self.cpu.clone(),
+            scanline: self.scanline,
+            color_clock: self.color_clock,
+            frame_count: self.frame_count,
+            last_frame_scanlines: self.last_frame_scanlines,
+            lines_without_wsync: self.lines_without_wsync,
+            tia_registers_touched: self.tia_registers_touched,
+        }
+    }
+
+    /// Puts the console back into `state`, `frame_count` included. The frame buffers keep the last
+    /// frame until the next `run`. A recording in progress follows the console: going back drops
+    /// the inputs made in the frames that will run again.
+    pub fn load_state(&mut self, state: &SaveState) {
+        if let Some(recording) = &mut self.recording {
+            recording.jump(self.frame_count, state.frame_count);
+        }
+
+        self.cpu = state.cpu.clone();
+        self.scanline = state.scanline;
+        self.color_clock = state.color_clock;
+        self.frame_count = state.frame_count;
+        self.last_frame_scanlines = state.last_frame_scanlines;
+        self.lines_without_wsync = state.lines_without_wsync;
+        self.tia_registers_touched = state.tia_registers_touched;
+    }
+
+    /// Keeps a save state for each of the last `frames` frames `run` completes, so `rewind` can go
+    /// back to them. The newest state is the current one, so `rewind` reaches at most `frames - 1`
+    /// frames back. 0, the default, turns this off and drops the history.
+    ///
+    /// Each state is a copy of the whole console, including the cartridge ROM: about 6K, so a
+    /// minute of NTSC frames takes about 20MB. Taking one adds roughly a tenth to the time a frame
+    /// takes (see the `frames` benchmark).
+    pub fn set_rewind_length(&mut self, frames: usize) {
+        self.rewind_length = frames;
+        while self.rewind_history.len() > frames {
+            self.rewind_history.pop_front();
+        }
+    }
+
+    /// Goes back `frames` frames, or as far as the rewind history reaches, and returns how many
+    /// frames it went back. Partway through a frame, e.g. after `step_instruction`, going back to
+    /// its start counts as the first frame. `rewind(0)` leaves the console alone. Like
+    /// `load_state`, the frame buffers keep the last frame until the next `run`.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let Some(latest) = self.rewind_history.back() else {
+            return 0;
+        };
+        if frames == 0 {
+            return 0;
+        }
+
+        // The newest state is the current one, unless the console has run on since
+        let partial = (
+            latest.cpu.total_cycles(),
+            latest.scanline,
+            latest.color_clock,
+        ) != (self.total_cycles(), self.scanline, self.color_clock);
+        let back = (frames - partial as usize).min(self.rewind_history.len() - 1);
+        self.rewind_history
+            .truncate(self.rewind_history.len() - back);
+
+        let state = self.rewind_history.back().unwrap().clone();
+        self.load_state(&state);
+        if partial {
+            // Inputs made during the partial frame were undone with it
+            if let Some(recording) = &mut self.recording {
+                recording.drop_current_frame();
+            }
+        }
+        back + partial as usize
+    }
+
+    /// Runs one frame from `state` and returns it, e.g. to make a thumbnail for a save slot. The
+    /// console is put back the way it was afterwards, only the frame buffers keep the rendered
+    /// frame. The frame isn't reported to the sync or diagnostics callbacks, nor recorded.
+    #[cfg(feature = "image")]
+    pub fn render_from_state(&mut self, state: &SaveState) -> &[[Rgba<u8>; 160]; 192] {
+        // The callbacks and the recording carry on from the current frame, they don't see the
+        // detour
+        let recording = self.recording.take();
+        let sync_callback = self.sync_callback.take();
+        let diagnostics_callback = self.diagnostics_callback.take();
+        let current = self.save_state();
+        self.load_state(state);
+        // Loading `current` turns them back on if they were
+        self.set_register_warnings(false);
+        self.run_frame();
+        self.load_state(&current);
+        self.recording = recording;
+        self.sync_callback = sync_callback;
+        self.diagnostics_callback = diagnostics_callback;
+
+        &self.frame_pixels
+    }
+
+    /// Starts recording every `KeyEvent` call, numbered by the frame it's made before. Any
+    /// recording in progress is discarded.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(InputRecorder::default());
+    }
+
+    /// Stops recording and returns the inputs made since `start_recording`, or nothing if it
+    /// wasn't recording.
+    pub fn stop_recording(&mut self) -> Vec<InputEvent> {
+        self.recording
+            .take()
+            .map(InputRecorder::into_events)
+            .unwrap_or_default()
+    }
+
+    /// Replays a recording from the current frame: each event is applied before its frame runs.
+    /// Returns once the last event has been applied, with the number of frames run. Starting from
+    /// the same state the recording did, e.g. after `power_cycle` with a fixed `RamInit`, the
+    /// console ends up in the same state.
+    pub fn play_recording(&mut self, log: &[InputEvent]) -> u64 {
+        let mut frame = 0;
+        for event in log {
+            while frame < event.frame {
+                self.run();
+                frame += 1;
+            }
+            self.input(event.kind);
+        }
+        frame
+    }
+
+    /// Connects a paddle and sets its position, or disconnects it with `None`. Ports 0 and 1 are
+    /// the left controller's pair, read through INPT0/INPT1, and ports 2 and 3 the right's, read
+    /// through INPT2/INPT3. A higher position takes longer to charge, one scanline per step.
+    ///
+    /// Panics if `port` is greater than 3.
+    pub fn set_paddle(&mut self, port: usize, position: Option<u8>) {
+        self.tia_mut().set_paddle(port, position);
+    }
+
+    /// Plugs a different controller into the left (0) or right (1) port.
+    ///
+    /// Panics if `port` is greater than 1.
+    pub fn set_controller(&mut self, port: usize, controller: Controller) {
+        self.cpu.bus_mut().set_controller(port, controller);
+    }
+
+    /// Moves the mouse plugged into either port by `set_controller`. The movement is
+    /// played out one step per scanline, so that games polling the mouse see every step.
+    pub fn mouse_move(&mut self, dx: i8, dy: i8) {
+        self.riot_mut().mouse_move(dx, dy);
+    }
+
+    /// Turns the wheel of the driving controller plugged into the left (0) or right (1) port by
+    /// `set_controller`, by `delta` steps of its encoder, clockwise if positive. Like the mouse's,
+    /// the steps are played out one per scanline.
+    ///
+    /// Panics if `port` is greater than 1.
+    pub fn driving_turn(&mut self, port: usize, delta: i8) {
+        self.riot_mut().driving_turn(port, delta);
+    }
+
+    /// Presses or releases a direction of one of up to four joysticks. Joysticks 0 and 1 are in the
+    /// left and right ports; 2 and 3 are the second joysticks of QuadTaris plugged into them by
+    /// `set_controller`. The `KeyEvent` methods drive joystick 0.
+    ///
+    /// Panics if `index` is greater than 3.
+    pub fn set_joystick(&mut self, index: u8, direction: Direction, pressed: bool) {
+        self.cpu
+            .bus_mut()
+            .set_joystick(index.into(), direction, pressed);
+    }
+
+    /// Presses or releases the fire button of one of up to four joysticks, see `set_joystick`.
+    ///
+    /// Panics if `index` is greater than 3.
+    pub fn set_joystick_fire(&mut self, index: u8, pressed: bool) {
+        self.cpu.bus_mut().set_joystick_fire(index.into(), pressed);
+    }
+
+    /// Presses or releases a button of the Genesis pad plugged into the left (0) or right (1) port
+    /// by `set_controller`.
+    ///
+    /// Panics if `port` is greater than 1.
+    pub fn set_genesis_button(&mut self, port: usize, button: GenesisButton, pressed: bool) {
+        self.cpu.bus_mut().set_genesis_button(port, button, pressed);
+    }
+
+    // Applies an input, recording it if a recording is in progress
+    fn input(&mut self, kind: InputKind) {
+        if let Some(recording) = &mut self.recording {
+            recording.record(kind);
+        }
+
+        match kind {
+            InputKind::Up(pressed) => self.set_joystick(0, Direction::Up, pressed),
+            InputKind::Down(pressed) => self.set_joystick(0, Direction::Down, pressed),
+            InputKind::Left(pressed) => self.set_joystick(0, Direction::Left, pressed),
+            InputKind::Right(pressed) => self.set_joystick(0, Direction::Right, pressed),
+            InputKind::Select(pressed) => self.riot_mut().select(pressed),
+            InputKind::Reset(pressed) => self.riot_mut().reset(pressed),
+            InputKind::JoystickFire(pressed) => self.set_joystick_fire(0, pressed),
+            InputKind::Color => self.riot_mut().color(),
+        }
+    }
+
+    /// Overlays the CPU registers and the current scanline onto the top rows of `frame_pixels`.
+    #[cfg(feature = "image")]
+    pub fn set_debug_hud(&mut self, enabled: bool) {
+        self.debug_hud = enabled;
+    }
+
     pub fn run(&mut self) {
-        // VSync
-        while self.tia.borrow().in_vsync() {
+        self.run_frame_stats();
+    }
+
+    /// Returns an iterator that runs a frame on every call to `next`, and yields its color codes
+    /// as `frame_indices` returns them. It runs for as long as it's asked to, so bound it:
+    ///
+    /// ```
+    /// let rom = std::fs::read("example_rom/garden.bin").unwrap();
+    /// let mut emulator = atari2600_lib::init_emulator_from_bytes(rom).unwrap();
+    /// let black_frames = emulator
+    ///     .frames()
+    ///     .take(60)
+    ///     .filter(|frame| frame.iter().flatten().all(|&color| color == 0))
+    ///     .count();
+    /// assert!(black_frames < 60);
+    /// ```
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames { emulator: self }
+    }
+
+    /// Runs a frame like `run`, and returns how its scanlines were spent.
+    pub fn run_frame_stats(&mut self) -> FrameStats {
+        let stats = self.run_frame();
+        self.frame_count += 1;
+        self.last_frame_scanlines = Some(self.scanline);
+        self.tia_registers_touched = self.tia_mut().take_registers_written();
+        if let Some(recording) = &mut self.recording {
+            recording.end_frame();
+        }
+        if self.rewind_length > 0 {
+            if self.rewind_history.len() == self.rewind_length {
+                self.rewind_history.pop_front();
+            }
+            self.rewind_history.push_back(self.save_state());
+        }
+
+        #[cfg(feature = "std")]
+        {
+            if self.frame_times.len() == FPS_WINDOW {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(Instant::now());
+        }
+
+        stats
+    }
+
+    // Runs a frame into the frame buffers, without counting it towards `measured_fps`
+    fn run_frame(&mut self) -> FrameStats {
+        let mut stats = FrameStats::default();
+        self.scanline = 0;
+        self.lines_without_wsync = 0;
+        self.full_frame.fill([0; 160]);
+
+        // VSync. A jammed CPU can't end any of these, so they're cut short.
+        while self.tia().in_vsync() && !self.cpu.jammed() {
             self.scanline();
+            stats.vsync_lines += 1;
         }
 
         // VBlank
-        while self.tia.borrow().in_vblank() {
-            self.scanline();
+        while !self.visible_scanline() && !self.cpu.jammed() {
+            stats.vblank_lines += 1;
+            self.capture_top_line();
         }
+        self.capture_scanline(0);
+        stats.visible_lines += 1;
 
-        for i in 0..192 {
-            if self.tia.borrow().in_vblank() {
+        // Row of `full_frame` the next line goes into, counted from the first visible line
+        let mut row = 1;
+        for i in 1..192 {
+            let visible = self.visible_scanline();
+            if !visible {
+                self.capture_full_frame_line(row);
+                row += 1;
+                stats.overscan_lines += 1;
                 break;
             }
-            self.scanline();
+            self.capture_scanline(i);
+            row += 1;
+            stats.visible_lines += 1;
+        }
 
-            let borrowed_tia = self.tia.borrow();
-            let array: &[Rgba<u8>] = borrowed_tia.get_scanline_pixels();
-            self.frame_pixels[i] = array.try_into().expect("Conversion failed");
+        // Overscan. Lines past the 192 that fit in the frame buffers still count as visible.
+        while !self.tia().in_vsync() && !self.cpu.jammed() {
+            if self.visible_scanline() {
+                stats.visible_lines += 1;
+            } else {
+                stats.overscan_lines += 1;
+            }
+            self.capture_full_frame_line(row);
+            row += 1;
         }
 
-        // Overscan
-        while !self.tia.borrow().in_vsync() {
-            self.scanline();
+        #[cfg(feature = "image")]
+        if self.debug_hud {
+            self.draw_debug_hud();
         }
+
+        stats.scanlines = self.scanline;
+        stats.lines_without_wsync = self.lines_without_wsync;
+        stats
+    }
+
+    /// The number of frames `run` has completed since the console was powered on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Calls `callback` whenever the ROM turns VSYNC or VBLANK on or off, e.g. to spot frames
+    /// with a missing VSYNC. Writes that don't change the signal aren't reported.
+    pub fn set_sync_callback(&mut self, callback: Box<dyn FnMut(SyncEvent) + Send>) {
+        self.sync_callback = Some(callback);
     }
 
-    fn handle_riot_clock(&self, c: usize) {
-        if c % 3 == 0 {
-            self.riot.borrow_mut().clock();
+    /// Checks CPU accesses for register misuse that is probably a bug, such as reading RESP0 or
+    /// writing the console switches. Each one is logged as a warning with the PC of the instruction
+    /// that made it, and passed to the callback set with `set_diagnostics_callback`. Off by default.
+    pub fn set_register_warnings(&mut self, enabled: bool) {
+        self.cpu
+            .bus_mut()
+            .diagnostics_mut()
+            .set_register_warnings(enabled);
+    }
+
+    /// Calls `callback` with each warning found while `set_register_warnings` is on, e.g. for a
+    /// frontend to show them.
+    pub fn set_diagnostics_callback(&mut self, callback: Box<dyn FnMut(RegisterWarning) + Send>) {
+        self.diagnostics_callback = Some(callback);
+    }
+
+    /// Returns the scanline and the color clock within it that the TIA renders next, e.g. to
+    /// see where a raster split happens when stepping with `advance` or `step_instruction`.
+    /// Scanlines are counted from the start of the frame `run` last started, color clocks from
+    /// the start of horizontal blank, so the visible picture starts at color clock 68.
+    pub fn beam_position(&self) -> (usize, usize) {
+        (self.scanline, self.color_clock)
+    }
+
+    /// Runs exactly `master_clocks` TIA color clocks, independently of frame boundaries. This lets
+    /// a host with its own clock keep the emulator in step at a finer grain than `run`.
+    pub fn advance(&mut self, master_clocks: u64) {
+        for _ in 0..master_clocks {
+            self.clock();
+        }
+    }
+
+    /// Continues execution at `addr` without going through the reset vector, e.g. to test a
+    /// routine on its own after setting up memory with `poke`. The instruction in progress, if
+    /// any, is abandoned.
+    pub fn set_pc(&mut self, addr: u16) {
+        self.cpu.set_pc(addr);
+    }
+
+    /// Runs until the CPU completes its next instruction. The TIA and RIOT keep running alongside
+    /// it, including while the CPU is halted by WSYNC. Once the CPU has jammed, it returns at once.
+    pub fn step_instruction(&mut self) {
+        let executed = self.cpu.instructions_executed();
+        while self.cpu.instructions_executed() == executed && !self.cpu.jammed() {
+            self.clock();
+        }
+    }
+
+    /// Runs `n` instructions and returns a trace of them, e.g. to see what a routine does line by
+    /// line. Each entry holds the registers from just before its instruction, and the beam
+    /// position its first cycle starts at, after any WSYNC halt ahead of it. The TIA and RIOT keep
+    /// running alongside, as with `step_instruction`. An instruction already in progress is
+    /// finished first, without being traced. The trace stops early at a JAM.
+    pub fn run_instructions(&mut self, n: usize) -> Vec<TraceEntry> {
+        let mut trace = Vec::with_capacity(n);
+        for _ in 0..n {
+            if self.cpu.jammed() {
+                break;
+            }
+
+            // Finish the instruction in progress, or the cycles a taken branch adds after it has
+            // been executed
+            while self.cpu.instruction_in_progress() {
+                self.clock();
+            }
+            let cpu = self.cpu.snapshot();
+            let len = trace::instruction_len(self.peek(cpu.pc));
+            let bytes: Vec<u8> = (0..len as u16)
+                .map(|i| self.peek(cpu.pc.wrapping_add(i)))
+                .collect();
+
+            // Clock up to the fetch, then run the rest of the instruction
+            let mut beam = self.beam_position();
+            while !self.cpu.instruction_in_progress() && !self.cpu.jammed() {
+                beam = self.beam_position();
+                self.clock();
+            }
+            self.step_instruction();
+
+            trace.push(TraceEntry {
+                pc: cpu.pc,
+                disassembly: trace::disassemble(cpu.pc, &bytes),
+                bytes,
+                a: cpu.a,
+                x: cpu.x,
+                y: cpu.y,
+                p: cpu.p,
+                sp: cpu.sp,
+                cycles: cpu.cycles,
+                scanline: beam.0,
+                // The clock the cycle starts on, rather than the one the CPU is clocked on
+                color_clock: beam.1 - CPU_PHASE,
+            });
         }
+        trace
+    }
+
+    /// Runs whole instructions until the CPU has run at least `target` cycles since it was reset,
+    /// and returns the cycle count reached. That's at most one instruction past `target`, or the
+    /// current count if it's already there. It stops short if the CPU jams.
+    pub fn run_until_cycle(&mut self, target: u64) -> u64 {
+        while self.total_cycles() < target && !self.cpu.jammed() {
+            self.step_instruction();
+        }
+        self.total_cycles()
+    }
+
+    /// Takes a snapshot of the CPU alone, e.g. to try out a few instructions in a debugger and
+    /// go back. Unlike `save_state`, it works in the middle of an instruction.
+    pub fn cpu_snapshot(&self) -> CpuSnapshot {
+        self.cpu.snapshot()
+    }
+
+    /// Puts the CPU back into `snapshot`. The rest of the console is left as it is.
+    pub fn restore_cpu(&mut self, snapshot: CpuSnapshot) {
+        self.cpu.restore(snapshot);
+    }
+
+    /// Makes JMP ($xxFF) read its target from across the page boundary, as the 65C02 does, for
+    /// code written for it. The 6507 in the console is NMOS, so by default the high byte comes from
+    /// the start of the same page.
+    pub fn set_cmos_jmp(&mut self, enabled: bool) {
+        self.cpu.set_cmos_jmp(enabled);
+    }
+
+    /// Signals an NMI, as if the 6507 had the 6502's NMI pin. The console doesn't, so no ROM
+    /// expects one, but a debugger or test harness can use it to break into the running program.
+    /// After the current instruction, the CPU pushes PC and P and jumps through the vector at
+    /// $FFFA, whatever the I flag.
+    pub fn trigger_nmi(&mut self) {
+        self.cpu.trigger_nmi();
     }
 
+    /// Drives the IRQ line, which the 6507 doesn't have either, see `trigger_nmi`. While it's
+    /// asserted and the I flag is clear, the CPU jumps through the vector at $FFFE after every
+    /// instruction.
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.cpu.set_irq(asserted);
+    }
+
+    /// Whether the CPU has run into one of the JAM opcodes, which lock up a real 6507. It stays
+    /// halted until `power_cycle`, while the TIA and RIOT keep running: `run` goes on returning
+    /// frames of whatever the TIA was left showing.
+    pub fn cpu_jammed(&self) -> bool {
+        self.cpu.jammed()
+    }
+
+    /// The number of cycles the CPU has run since it was last reset. Cycles spent halted on WSYNC
+    /// aren't counted, since the CPU isn't clocked then.
+    pub fn total_cycles(&self) -> u64 {
+        self.cpu.total_cycles()
+    }
+
+    /// The number of instructions the CPU has completed since it was last reset.
+    pub fn instructions_executed(&self) -> u64 {
+        self.cpu.instructions_executed()
+    }
+
+    #[cfg(feature = "image")]
+    fn draw_debug_hud(&mut self) {
+        let cpu = &self.cpu;
+        let lines = [
+            format!(
+                "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X}",
+                cpu.pc, cpu.a, cpu.x, cpu.y
+            ),
+            format!("SP:{:02X} SL:{}", cpu.sp(), self.scanline),
+        ];
+        hud::draw(&mut self.frame_pixels, &lines);
+    }
+
+    /// Runs up to `max_frames` frames looking for the point where the boot sequence settles into
+    /// a stable, content-rich frame, e.g. to capture a thumbnail. Emulation stops on the detected
+    /// frame, so the frame buffers hold it when this returns. Returns the index of that frame, or the
+    /// index of the last frame run if none was found.
+    pub fn find_title_frame(&mut self, max_frames: usize) -> usize {
+        let mut stable_frames = 0;
+        let mut previous = self.frame_indices;
+
+        for i in 0..max_frames {
+            self.run();
+
+            let content_rich = distinct_colors(&self.frame_indices) >= TITLE_MIN_COLORS;
+            let changed = changed_pixels(&previous, &self.frame_indices);
+            let stable = changed <= (160 * 192) / TITLE_MAX_CHANGE_DIVISOR;
+
+            if content_rich && stable {
+                stable_frames += 1;
+                if stable_frames == TITLE_STABLE_FRAMES {
+                    return i;
+                }
+            } else {
+                stable_frames = 0;
+            }
+
+            previous = self.frame_indices;
+        }
+
+        max_frames.saturating_sub(1)
+    }
+
+    fn tia(&self) -> &TIA {
+        self.cpu.bus().tia()
+    }
+
+    fn tia_mut(&mut self) -> &mut TIA {
+        self.cpu.bus_mut().tia_mut()
+    }
+
+    fn riot(&self) -> &RIOT {
+        self.cpu.bus().riot()
+    }
+
+    fn riot_mut(&mut self) -> &mut RIOT {
+        self.cpu.bus_mut().riot_mut()
+    }
+
+    fn handle_riot_clock(&mut self, c: usize) {
+        if c % 3 == 1 {
+            self.riot_mut().clock();
+        }
+    }
+
+    // The CPU runs at a third of the color clock, and a cycle's bus access happens at its end. So
+    // the CPU is clocked on the last color clock of every three, after the TIA has drawn it: a
+    // write in the cycle covering clocks 3k to 3k+2 shows from clock 3k+3 on. A line is 76 cycles
+    // long, so every line starts with a cycle at clock 0, which is where WSYNC releases the CPU.
     fn handle_cpu_clock(&mut self, c: usize) {
-        if !self.tia.borrow().cpu_halt() && c % 3 == 2 {
+        if !self.tia().cpu_halt() && c % 3 == CPU_PHASE {
             self.cpu.clock();
+
+            if let Some(kind) = self.tia_mut().take_sync_change() {
+                if let Some(callback) = &mut self.sync_callback {
+                    callback(SyncEvent {
+                        kind,
+                        scanline: self.scanline,
+                        frame: self.frame_count,
+                    });
+                }
+            }
+
+            while let Some((address, kind)) =
+                self.cpu.bus_mut().diagnostics_mut().take_register_warning()
+            {
+                let warning = RegisterWarning {
+                    pc: self.cpu.instruction_address(),
+                    address,
+                    kind,
+                };
+                warn!("{}", warning);
+                if let Some(callback) = &mut self.diagnostics_callback {
+                    callback(warning);
+                }
+            }
+        }
+    }
+
+    // Runs color clock `color_clock` of the line. The TIA draws it, and WSYNC releases the CPU on
+    // clock 0, where the TIA starts the line's HBLANK.
+    fn clock(&mut self) {
+        let c = self.color_clock;
+        self.handle_riot_clock(c);
+        self.tia_mut().clock();
+        self.handle_cpu_clock(c);
+
+        self.color_clock += 1;
+        if self.color_clock == CLOCKS_PER_SCANLINE {
+            self.color_clock = 0;
+            self.scanline += 1;
+            if !self.tia_mut().take_wsync_written() {
+                self.lines_without_wsync += 1;
+            }
         }
     }
 
     fn scanline(&mut self) {
-        for c in 0..CLOCKS_PER_SCANLINE {
-            self.handle_riot_clock(c);
-            self.tia.borrow_mut().clock();
-            self.handle_cpu_clock(c);
+        for _ in 0..CLOCKS_PER_SCANLINE {
+            self.clock();
+        }
+    }
+
+    // Runs the rest of the current scanline, and returns whether it's part of the picture. That's
+    // decided once horizontal blanking is over, since games usually turn VBLANK off or on right
+    // after the WSYNC that starts a line.
+    fn visible_scanline(&mut self) -> bool {
+        let mut visible = !self.tia().in_vblank();
+        loop {
+            if self.color_clock == H_BLANK_CLOCKS {
+                visible = !self.tia().in_vblank();
+            }
+            self.clock();
+            if self.color_clock == 0 {
+                return visible;
+            }
+        }
+    }
+
+    // Copies the scanline the TIA has just drawn into row `row` of the frame buffers
+    fn capture_scanline(&mut self, row: usize) {
+        let tia = self.cpu.bus().tia();
+        self.frame_indices[row] = *tia.get_scanline_indices();
+        #[cfg(feature = "image")]
+        {
+            self.frame_pixels[row] = *tia.get_scanline_pixels();
+        }
+        self.capture_full_frame_line(row);
+    }
+
+    // Copies the scanline the TIA has just drawn into `full_frame`, `row` lines after the first
+    // visible line. Lines past the bottom of it are dropped.
+    fn capture_full_frame_line(&mut self, row: usize) {
+        let index = self.extra_lines.0 + row;
+        if index < self.full_frame.len() {
+            self.full_frame[index] = *self.cpu.bus().tia().get_scanline_indices();
+        }
+    }
+
+    // Copies a VBLANK line into the top of `full_frame`. The first visible line isn't known yet,
+    // so the lines scroll up to keep the most recent ones.
+    fn capture_top_line(&mut self) {
+        let top = self.extra_lines.0;
+        if top > 0 {
+            self.full_frame[..top].rotate_left(1);
+            self.full_frame[top - 1] = *self.cpu.bus().tia().get_scanline_indices();
         }
     }
 }
 
+fn distinct_colors(frame: &[[u8; 160]; 192]) -> usize {
+    let mut seen = [false; 128];
+    for &index in frame.iter().flatten() {
+        seen[index as usize] = true;
+    }
+    seen.iter().filter(|&&s| s).count()
+}
+
+fn changed_pixels(a: &[[u8; 160]; 192], b: &[[u8; 160]; 192]) -> usize {
+    a.iter()
+        .flatten()
+        .zip(b.iter().flatten())
+        .filter(|(a, b)| a != b)
+        .count()
+}
+
 pub trait KeyEvent {
     fn up(&mut self, pressed: bool);
     fn down(&mut self, pressed: bool);
@@ -105,65 +1265,1060 @@ pub trait KeyEvent {
 
 impl KeyEvent for EmulatorCore {
     fn up(&mut self, pressed: bool) {
-        self.riot.borrow_mut().up(pressed);
+        self.input(InputKind::Up(pressed));
     }
 
     fn down(&mut self, pressed: bool) {
-        self.riot.borrow_mut().down(pressed);
+        self.input(InputKind::Down(pressed));
     }
 
     fn left(&mut self, pressed: bool) {
-        self.riot.borrow_mut().left(pressed);
+        self.input(InputKind::Left(pressed));
     }
 
     fn right(&mut self, pressed: bool) {
-        self.riot.borrow_mut().right(pressed);
+        self.input(InputKind::Right(pressed));
     }
 
     fn reset(&mut self, pressed: bool) {
-        self.riot.borrow_mut().reset(pressed);
+        self.input(InputKind::Reset(pressed));
     }
 
     fn select(&mut self, pressed: bool) {
-        self.riot.borrow_mut().select(pressed);
+        self.input(InputKind::Select(pressed));
     }
 
     fn joystick_fire(&mut self, pressed: bool) {
-        self.tia.borrow_mut().joystick_fire(pressed);
+        self.input(InputKind::JoystickFire(pressed));
     }
 
     fn color(&mut self) {
-        self.riot.borrow_mut().color();
+        self.input(InputKind::Color);
     }
 }
 
-fn initialize_components<P: AsRef<str>>(
-    rom_path: P,
-) -> Result<(SharedRIOT, SharedTIA, CPU6507), Box<dyn Error>> {
-    let mut fh = File::open(rom_path.as_ref()).expect("unable to open rom");
+// Checks that the ROM fits the cartridge address space
+fn validate_rom_size(rom: &[u8]) -> Result<(), Box<dyn Error>> {
+    match rom.len() {
+        CARTRIDGE_SIZE | HALF_CARTRIDGE_SIZE => Ok(()),
+        len => Err(format!(
+            "unsupported ROM size: {} bytes (only 2K and 4K cartridges are supported)",
+            len
+        )
+        .into()),
+    }
+}
 
-    let mut rom = vec![];
-    let bytes = fh.read_to_end(&mut rom).expect("unable to read rom data");
-    info!("ROM: {} ({} bytes)", rom_path.as_ref(), bytes);
+fn initialize_components(
+    rom: Vec<u8>,
+    options: &EmulatorOptions,
+) -> Result<CPU6507<AtariBus>, Box<dyn Error>> {
+    validate_rom_size(&rom)?;
 
     info!("RIOT: init");
-    let riot = Rc::new(RefCell::new(RIOT::new()));
-    riot.borrow_mut().up(false);
-    riot.borrow_mut().down(false);
-    riot.borrow_mut().left(false);
-    riot.borrow_mut().right(false);
-    riot.borrow_mut().select(false);
-    riot.borrow_mut().reset(false);
+    let mut riot = RIOT::new_with_ram_init(options.ram_init);
+    riot.set_difficulty(0, options.difficulty_hard[0]);
+    riot.set_difficulty(1, options.difficulty_hard[1]);
+    riot.set_tv_type(options.color_tv);
+    riot.select(false);
+    riot.reset(false);
 
     info!("TIA: init");
-    let tia = Rc::new(RefCell::new(TIA::new()));
-    tia.borrow_mut().joystick_fire(false);
+    let tia = TIA::new();
 
-    let bus = AtariBus::new(tia.clone(), riot.clone(), rom);
+    // Start with every controller's inputs released
+    let mut bus = AtariBus::new(tia, riot, Diagnostics::new(), rom);
+    bus.update_controllers();
 
     info!("CPU: init");
-    let mut cpu = CPU6507::new(Box::new(bus));
+    let mut cpu = CPU6507::new(bus);
     cpu.reset();
 
-    Ok((riot, tia, cpu))
+    Ok(cpu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::Asm;
+
+    const TEST_ROM: &[u8] = include_bytes!("../example_rom/garden.bin");
+
+    // Builds a 4K ROM that starts running `program` from $F000
+    fn rom_with_program(program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0xea; CARTRIDGE_SIZE];
+        rom[..program.len()].copy_from_slice(program);
+        rom[0xffc..0xffe].copy_from_slice(&[0x00, 0xf0]);
+        rom
+    }
+
+    #[test]
+    fn find_title_frame_settles_on_stable_frame() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        let frame = emu.find_title_frame(120);
+
+        assert!(frame > 0);
+        assert!(frame < 119);
+        assert!(distinct_colors(emu.frame_indices()) >= TITLE_MIN_COLORS);
+
+        let title = *emu.frame_indices();
+        emu.run();
+        assert_eq!(changed_pixels(&title, emu.frame_indices()), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn from_bytes_matches_from_file() {
+        let mut from_file = init_emulator("example_rom/garden.bin").unwrap();
+        let mut from_bytes = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+
+        for _ in 0..10 {
+            from_file.run();
+            from_bytes.run();
+        }
+        assert_eq!(from_file.frame_indices(), from_bytes.frame_indices());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn missing_rom_is_an_error() {
+        let err = init_emulator("/nonexistent")
+            .err()
+            .expect("loading should fail");
+        assert!(err.to_string().contains("/nonexistent"));
+    }
+
+    #[test]
+    fn half_size_rom_starts_at_reset_vector() {
+        // NOPs, then JMP $F010, with the reset vector in the last bytes of the 2K image
+        let mut rom = vec![0xea; HALF_CARTRIDGE_SIZE];
+        rom[0x10..0x13].copy_from_slice(&[0x4c, 0x10, 0xf0]);
+        rom[0x7fc..0x7fe].copy_from_slice(&[0x08, 0xf0]);
+
+        let mut emu = init_emulator_from_bytes(rom).unwrap();
+        assert_eq!(emu.cpu.pc, 0xf008);
+
+        for _ in 0..10 {
+            emu.step_instruction();
+        }
+        assert_eq!(emu.cpu.pc & 0x1fff, 0x1010);
+    }
+
+    #[test]
+    fn unsupported_rom_sizes_are_errors() {
+        for len in [0, 1024, 3000, 8192] {
+            assert!(
+                init_emulator_from_bytes(vec![0; len]).is_err(),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn ram_init_option_reaches_riot() {
+        use crate::{bus::Bus, memory::PiaAddress};
+
+        let options = EmulatorOptions::new().ram_init(RamInit::Random(7));
+        let mut emu = init_emulator_from_bytes_with(TEST_ROM.to_vec(), options).unwrap();
+        let mut expected = RIOT::new_with_ram_init(RamInit::Random(7));
+
+        for addr in 0..128 {
+            assert_eq!(
+                emu.cpu.bus_mut().read(0x80 + addr as u16),
+                expected.read(PiaAddress::RAM(addr))
+            );
+        }
+    }
+
+    #[test]
+    fn options_set_switches_and_region() {
+        use crate::memory::PiaAddress;
+
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        assert_eq!(emu.region(), Region::Ntsc);
+        assert_eq!(
+            emu.riot_mut().read(PiaAddress::SWCHB) & 0b1100_1000,
+            0b1100_1000
+        );
+
+        let options = EmulatorOptions::new()
+            .region(Region::Pal)
+            .difficulty(0, false)
+            .color_tv(false);
+        let mut emu = init_emulator_from_bytes_with(TEST_ROM.to_vec(), options).unwrap();
+        assert_eq!(emu.region(), Region::Pal);
+        assert_eq!(
+            emu.riot_mut().read(PiaAddress::SWCHB) & 0b1100_1000,
+            0b1000_0000
+        );
+    }
+
+    #[test]
+    fn colors_reads_back_color_registers() {
+        use crate::memory::TiaWriteAddress;
+
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        {
+            let tia = emu.tia_mut();
+            tia.write(TiaWriteAddress::COLUP0, 0x11);
+            tia.write(TiaWriteAddress::COLUP1, 0x22);
+            tia.write(TiaWriteAddress::COLUPF, 0x33);
+            tia.write(TiaWriteAddress::COLUBK, 0xff);
+        }
+
+        assert_eq!(
+            emu.colors(),
+            ColorRegisters {
+                colup0: 0x10,
+                colup1: 0x22,
+                colupf: 0x32,
+                colubk: 0xfe,
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn debug_hud_is_drawn_top_left() {
+        let mut plain = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        let mut hud = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        hud.set_debug_hud(true);
+
+        for _ in 0..3 {
+            plain.run();
+            hud.run();
+        }
+
+        let white = Rgba([0xff, 0xff, 0xff, 0xff]);
+        let lit = hud.frame_pixels()[..12]
+            .iter()
+            .flat_map(|row| &row[..92])
+            .filter(|&&p| p == white)
+            .count();
+        assert!(lit > 0);
+
+        // The rest of the frame is left alone
+        assert_eq!(hud.frame_pixels()[12..], plain.frame_pixels()[12..]);
+        assert_eq!(hud.frame_hash(), plain.frame_hash());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn to_rgb_image_matches_frame() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.find_title_frame(120);
+
+        let image = emu.to_rgb_image();
+        assert_eq!(image.dimensions(), (160, 192));
+
+        let [r, g, b, _] = emu.frame_pixels()[100][80].0;
+        assert_eq!(*image.get_pixel(80, 100), Rgb([r, g, b]));
+    }
+
+    #[test]
+    fn advance_runs_exact_color_clocks() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.run();
+        let (scanline, color_clock) = (emu.scanline, emu.color_clock);
+
+        emu.advance(CLOCKS_PER_SCANLINE as u64);
+        assert_eq!((emu.scanline, emu.color_clock), (scanline + 1, color_clock));
+
+        // Advancing in uneven steps ends up in the same place
+        emu.advance(100);
+        assert_eq!(emu.color_clock, color_clock + 100);
+        emu.advance(CLOCKS_PER_SCANLINE as u64 - 100);
+        assert_eq!((emu.scanline, emu.color_clock), (scanline + 2, color_clock));
+    }
+
+    #[test]
+    fn reset_held_across_power_cycle() {
+        use crate::memory::PiaAddress;
+
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.run();
+
+        emu.reset(true);
+        emu.power_cycle();
+        assert_eq!(emu.riot_mut().read(PiaAddress::SWCHB) & 0x01, 0);
+
+        emu.reset(false);
+        assert_eq!(emu.riot_mut().read(PiaAddress::SWCHB) & 0x01, 0x01);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn frame_indices_match_frame_pixels() {
+        use crate::tia::palette::NTSC_PALETTE;
+
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.find_title_frame(120);
+
+        for (indices, pixels) in emu.frame_indices().iter().zip(emu.frame_pixels()) {
+            for (&index, &pixel) in indices.iter().zip(pixels) {
+                assert_eq!(NTSC_PALETTE[index as usize], pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn emulated_fps_follows_region() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        assert_eq!(emu.region(), Region::Ntsc);
+        assert!((emu.emulated_fps() - 59.94).abs() < 0.05);
+
+        emu.set_region(Region::Pal);
+        assert!((emu.emulated_fps() - 50.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn replaying_a_recording_reproduces_the_frame() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        let session = |emu: &mut EmulatorCore, frame: u64| match frame {
+            5 => emu.reset(true),
+            8 => emu.reset(false),
+            20 => {
+                emu.right(true);
+                emu.joystick_fire(true);
+            }
+            35 => {
+                emu.right(false);
+                emu.joystick_fire(false);
+            }
+            _ => {}
+        };
+
+        emu.start_recording();
+        for frame in 0..60 {
+            session(&mut emu, frame);
+            emu.run();
+        }
+        let log = emu.stop_recording();
+        let recorded = emu.frame_hash();
+        assert_eq!(log.len(), 6);
+        assert_eq!(log[2].frame, 20);
+
+        emu.power_cycle();
+        let frames = emu.play_recording(&log);
+        assert_eq!(frames, 35);
+        for _ in frames..60 {
+            emu.run();
+        }
+        assert_eq!(emu.frame_hash(), recorded);
+    }
+
+    #[test]
+    fn recording_follows_rewind() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.set_rewind_length(10);
+        emu.start_recording();
+        for frame in 0..15 {
+            match frame {
+                5 => emu.right(true),
+                12 => emu.right(false),
+                _ => {}
+            }
+            emu.run();
+        }
+
+        // Frames 11 to 14 run again, and the input made before frame 12 never happened
+        assert_eq!(emu.rewind(4), 4);
+        emu.run();
+        emu.joystick_fire(true);
+        for _ in 12..20 {
+            emu.run();
+        }
+        let log = emu.stop_recording();
+        let recorded = emu.frame_hash();
+        assert_eq!(
+            log.iter().map(|event| event.frame).collect::<Vec<_>>(),
+            [5, 12]
+        );
+        assert_eq!(log[1].kind, InputKind::JoystickFire(true));
+
+        // A fresh console, `power_cycle` would keep the fire button held
+        let mut replay = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        let frames = replay.play_recording(&log);
+        for _ in frames..20 {
+            replay.run();
+        }
+        assert_eq!(replay.frame_count(), emu.frame_count());
+        assert_eq!(replay.total_cycles(), emu.total_cycles());
+        assert_eq!(replay.frame_hash(), recorded);
+    }
+
+    #[test]
+    fn peek_leaves_timer_flags_alone() {
+        const INTIM: u16 = 0x284;
+        const INSTAT: u16 = 0x285;
+        const TIM1T: u16 = 0x294;
+
+        // JMP $F000
+        let mut emu = init_emulator_from_bytes(rom_with_program(&[0x4c, 0x00, 0xf0])).unwrap();
+        emu.poke(TIM1T, 2);
+        emu.advance(30);
+
+        assert!(emu.timer_state().underflowed());
+        emu.peek(INTIM);
+        assert_eq!(emu.peek(INSTAT) & 0x80, 0x80);
+        assert!(emu.timer_state().underflowed());
+
+        emu.peek_raw(INTIM);
+        assert_eq!(emu.peek(INSTAT) & 0x80, 0);
+
+        // RAM, through a mirror that only differs in the unconnected address lines
+        emu.poke(0x80, 0x5a);
+        assert_eq!(emu.peek(0x2080), 0x5a);
+        assert_eq!(emu.peek_raw(0x80), 0x5a);
+        assert_eq!(emu.peek(0xf000), 0x4c);
+    }
+
+    #[test]
+    fn rewind_returns_to_earlier_frames() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.set_rewind_length(10);
+        assert_eq!(emu.rewind(1), 0);
+
+        // The title screen doesn't change, the cycle count tells the frames apart
+        let mut cycles = Vec::new();
+        for _ in 0..20 {
+            emu.run();
+            cycles.push(emu.total_cycles());
+        }
+
+        // Back to the end of frame 15, then frame 16 is run again
+        assert_eq!(emu.rewind(4), 4);
+        assert_eq!(emu.total_cycles(), cycles[15]);
+        assert_eq!(emu.frame_count(), 16);
+        emu.run();
+        assert_eq!(emu.total_cycles(), cycles[16]);
+        assert_eq!(emu.frame_count(), 17);
+
+        // The history starts at frame 10, and rewinding dropped frames 16 to 19 from it
+        assert_eq!(emu.rewind(100), 6);
+        assert_eq!(emu.total_cycles(), cycles[10]);
+        assert_eq!(emu.frame_count(), 11);
+
+        // Partway through a frame, rewind(0) keeps the progress and rewind(1) goes back to the
+        // frame's start
+        emu.step_instruction();
+        let stepped = emu.total_cycles();
+        assert_eq!(emu.rewind(0), 0);
+        assert_eq!(emu.total_cycles(), stepped);
+        assert_eq!(emu.rewind(1), 1);
+        assert_eq!(emu.total_cycles(), cycles[10]);
+        emu.step_instruction();
+        assert_eq!(emu.rewind(2), 1);
+        assert_eq!(emu.total_cycles(), cycles[10]);
+
+        emu.set_rewind_length(0);
+        emu.run();
+        assert_eq!(emu.rewind(1), 0);
+    }
+
+    #[test]
+    fn beam_position_follows_color_clocks() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        assert_eq!(emu.beam_position(), (0, POWER_ON_COLOR_CLOCK));
+
+        emu.advance(CLOCKS_PER_SCANLINE as u64 + 71);
+        assert_eq!(emu.beam_position(), (1, 72));
+
+        // A frame ends at the start of a scanline
+        emu.run();
+        assert_eq!(emu.beam_position(), (emu.last_frame_scanlines.unwrap(), 0));
+
+        emu.step_instruction();
+        let (scanline, color_clock) = emu.beam_position();
+        assert_eq!(scanline, emu.last_frame_scanlines.unwrap());
+        assert!(color_clock > 0);
+    }
+
+    #[test]
+    fn sync_callback_reports_transitions() {
+        use std::sync::{Arc, Mutex};
+
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        emu.set_sync_callback(Box::new(move |event| sink.lock().unwrap().push(event)));
+
+        for _ in 0..3 {
+            emu.run();
+        }
+
+        // The first frame is cut short by the boot sequence, the others run a whole frame
+        for frame in 1..3 {
+            let events: Vec<SyncEvent> = events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|e| e.frame == frame)
+                .copied()
+                .collect();
+            let kinds: Vec<SyncKind> = events.iter().map(|e| e.kind).collect();
+            assert_eq!(
+                kinds,
+                [
+                    SyncKind::VsyncEnd,
+                    SyncKind::VblankEnd,
+                    SyncKind::VblankStart,
+                    SyncKind::VsyncStart
+                ]
+            );
+            assert!(events.windows(2).all(|e| e[0].scanline < e[1].scanline));
+        }
+    }
+
+    #[test]
+    fn set_pc_runs_routine_without_reset_vector() {
+        let mut asm = Asm::new(0xf000);
+        asm.jmp_here();
+        let routine = asm.here();
+        asm.lda_zp(0x80).asl().sta_zp(0x81);
+        let mut emu = init_emulator_from_bytes(asm.rom()).unwrap();
+        emu.poke(0x80, 0x21);
+
+        // Switching in the middle of the JMP drops it
+        emu.advance(3);
+        emu.set_pc(routine);
+        for _ in 0..3 {
+            emu.step_instruction();
+        }
+        assert_eq!(emu.peek(0x81), 0x42);
+        assert_eq!(emu.cpu_snapshot().pc, 0xf008);
+    }
+
+    #[test]
+    fn run_instructions_traces_each_instruction() {
+        let mut asm = Asm::new(0xf000);
+        asm.ldx_imm(5);
+        let top = asm.here();
+        asm.sta_zp(0x02).dex().bne(top).jmp_here();
+        let mut emu = init_emulator_from_bytes(asm.rom()).unwrap();
+
+        let trace = emu.run_instructions(5);
+        let lines: Vec<_> = trace.iter().map(|e| e.disassembly.as_str()).collect();
+        assert_eq!(
+            lines,
+            ["LDX #$05", "STA $02", "DEX", "BNE $F002", "STA $02"]
+        );
+        assert_eq!(trace[2].bytes, [0xca]);
+        assert_eq!(trace[3].x, 4);
+        assert_eq!(
+            trace.iter().map(|e| e.cycles).collect::<Vec<_>>(),
+            [0, 2, 5, 7, 10]
+        );
+
+        // DEX waits for WSYNC to release the CPU at the start of the next line, and the taken
+        // branch takes 3 cycles
+        assert_eq!((trace[2].scanline, trace[2].color_clock), (1, 0));
+        assert_eq!((trace[3].scanline, trace[3].color_clock), (1, 6));
+        assert_eq!((trace[4].scanline, trace[4].color_clock), (1, 15));
+        assert_eq!(
+            trace[2].to_string(),
+            "F004  CA        DEX           A:00 X:05 Y:00 P:24 SP:FF CYC:5 SL:1 CLK:0"
+        );
+    }
+
+    #[test]
+    fn wsync_releases_cpu_at_start_of_next_line() {
+        // WSYNC written at a few different points of the line
+        for delay in [0, 7, 30] {
+            let mut asm = Asm::new(0xf000);
+            for _ in 0..delay {
+                asm.nop();
+            }
+            asm.sta_zp(0x02).nop().jmp_here();
+            let mut emu = init_emulator_from_bytes(asm.rom()).unwrap();
+
+            let trace = emu.run_instructions(delay + 2);
+            let (wsync, next) = (&trace[delay], &trace[delay + 1]);
+            assert_eq!(wsync.disassembly, "STA $02");
+            assert_eq!((next.scanline, next.color_clock), (wsync.scanline + 1, 0));
+            // The CPU isn't clocked while it's halted
+            assert_eq!(next.cycles, wsync.cycles + 3);
+        }
+    }
+
+    #[test]
+    fn tia_writes_land_at_end_of_cpu_cycle() {
+        const COLOR: u8 = 0x44;
+
+        for nops in [8usize, 9, 30] {
+            // Each line starts out black, and turns to COLOR partway through
+            let mut asm = Asm::new(0xf000);
+            asm.lda_imm(COLOR).ldx_imm(0);
+            let top = asm.here();
+            asm.sta_zp(0x02).stx_zp(0x09);
+            for _ in 0..nops {
+                asm.nop();
+            }
+            asm.sta_zp(0x09).jmp(top);
+            let mut emu = init_emulator_from_bytes(asm.rom()).unwrap();
+
+            // Run to the end of a line, so the TIA holds a whole one
+            emu.advance(4 * CLOCKS_PER_SCANLINE as u64);
+            let (_, color_clock) = emu.beam_position();
+            emu.advance((CLOCKS_PER_SCANLINE - color_clock) as u64);
+            let line = emu.tia().get_scanline_indices();
+            let first = line.iter().position(|&i| i == COLOR >> 1).unwrap();
+
+            // STA COLUBK ends on cycle `end` after WSYNC, so the TIA has drawn 3 * `end` color
+            // clocks by then. A write during HBLANK colors the whole line.
+            let end = 3 + 2 * nops + 3;
+            assert_eq!(
+                first,
+                (3 * end).saturating_sub(H_BLANK_CLOCKS),
+                "{} NOPs",
+                nops
+            );
+        }
+    }
+
+    #[test]
+    fn register_warnings_report_pc() {
+        use std::sync::{Arc, Mutex};
+
+        // LDA RESP0; LDA TIM64T; STA SWCHB; JMP *
+        let program = [
+            0xa5, 0x10, 0xad, 0x96, 0x02, 0x8d, 0x82, 0x02, 0x4c, 0x08, 0xf0,
+        ];
+        let mut emu = init_emulator_from_bytes(rom_with_program(&program)).unwrap();
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        emu.set_diagnostics_callback(Box::new(move |warning| sink.lock().unwrap().push(warning)));
+
+        // Nothing is checked until warnings are turned on
+        for _ in 0..4 {
+            emu.step_instruction();
+        }
+        assert!(warnings.lock().unwrap().is_empty());
+
+        emu.set_register_warnings(true);
+        emu.power_cycle();
+        for _ in 0..4 {
+            emu.step_instruction();
+        }
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            [
+                RegisterWarning {
+                    pc: 0xf000,
+                    address: 0x0010,
+                    kind: RegisterWarningKind::ReadOfWriteRegister(TiaWriteAddress::RESP0),
+                },
+                RegisterWarning {
+                    pc: 0xf002,
+                    address: 0x0296,
+                    kind: RegisterWarningKind::ReadOfTimerInterval(PiaAddress::TIM64T),
+                },
+                RegisterWarning {
+                    pc: 0xf005,
+                    address: 0x0282,
+                    kind: RegisterWarningKind::WriteToConsoleSwitches(PiaAddress::SWCHB),
+                },
+            ]
+        );
+        assert_eq!(
+            warnings.lock().unwrap()[0].to_string(),
+            "$F000: read of write only register RESP0 at $0010"
+        );
+    }
+
+    #[test]
+    fn frame_stats_add_up() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        // The first frame is cut short by the boot sequence
+        for frame in 0..3 {
+            let stats = emu.run_frame_stats();
+            assert_eq!(
+                stats.vsync_lines + stats.vblank_lines + stats.visible_lines + stats.overscan_lines,
+                stats.scanlines
+            );
+            assert_eq!(Some(stats.scanlines), emu.last_frame_scanlines);
+            assert!(stats.lines_without_wsync < stats.scanlines);
+
+            if frame == 1 {
+                assert!(stats.vsync_well_formed());
+                assert_eq!(stats.scanlines, 262);
+                assert_eq!(stats.visible_lines, 192);
+            }
+        }
+    }
+
+    #[test]
+    fn extra_lines_surround_the_picture() {
+        const VSYNC: u8 = 0x00;
+        const VBLANK: u8 = 0x01;
+        const WSYNC: u8 = 0x02;
+        const COLUBK: u8 = 0x09;
+
+        // A frame with a different background above, in and below the picture, one color per
+        // loop of WSYNCs. The TIA draws the background during VBLANK too.
+        let mut asm = Asm::new(0xf000);
+        let frame = asm.here();
+        asm.lda_imm(0x02).sta_zp(VSYNC).sta_zp(VBLANK);
+        asm.sta_zp(WSYNC).sta_zp(WSYNC).sta_zp(WSYNC);
+        asm.lda_imm(0x00).sta_zp(VSYNC);
+        for (color, lines) in [(0x1e, 20), (0x44, 192), (0x86, 30)] {
+            asm.lda_imm(color).sta_zp(COLUBK);
+            if color == 0x44 {
+                asm.lda_imm(0x00).sta_zp(VBLANK);
+            }
+            asm.ldx_imm(lines);
+            let line = asm.here();
+            asm.sta_zp(WSYNC).dex().bne(line);
+        }
+        asm.jmp(frame);
+
+        let options = EmulatorOptions::new().extra_lines(10, 20);
+        let mut emu = init_emulator_from_bytes_with(asm.rom(), options).unwrap();
+        let mut plain = init_emulator_from_bytes(asm.rom()).unwrap();
+        assert_eq!(plain.full_frame_indices().len(), 192);
+
+        for _ in 0..3 {
+            emu.run();
+            plain.run();
+        }
+
+        let full = emu.full_frame_indices();
+        assert_eq!(full.len(), 10 + 192 + 20);
+        let rows_of = |rows: &[[u8; 160]], index| rows.iter().flatten().all(|&i| i == index);
+        assert!(rows_of(&full[..10], 0x1e >> 1));
+        assert!(rows_of(&full[10..202], 0x44 >> 1));
+        assert!(rows_of(&full[202..], 0x86 >> 1));
+        assert_eq!(&full[10..202], &plain.frame_indices()[..]);
+        assert_eq!(plain.full_frame_indices(), &plain.frame_indices()[..]);
+
+        emu.set_extra_lines(0, 0);
+        emu.run();
+        assert_eq!(emu.full_frame_indices().len(), 192);
+    }
+
+    #[test]
+    fn run_until_cycle_stops_after_target() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+
+        for target in [1, 1_000, 50_000] {
+            let reached = emu.run_until_cycle(target);
+            assert!(reached >= target);
+            // No instruction takes more than 7 cycles
+            assert!(reached < target + 7, "{} for target {}", reached, target);
+            assert_eq!(emu.total_cycles(), reached);
+        }
+
+        // Already past the target, nothing is run
+        let instructions = emu.instructions_executed();
+        assert_eq!(emu.run_until_cycle(10), emu.total_cycles());
+        assert_eq!(emu.instructions_executed(), instructions);
+    }
+
+    #[test]
+    fn target_fps_follows_frame_length() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        assert_eq!(emu.clocks_per_frame(), 228 * 262);
+
+        for _ in 0..3 {
+            emu.run();
+        }
+        let clocks = emu.clocks_per_frame();
+        assert_eq!(clocks % CLOCKS_PER_SCANLINE, 0);
+        assert_eq!(emu.target_fps(), 3_579_545.0 / clocks as f64);
+
+        // The game's frame length stays the same, but the clock changes
+        emu.set_region(Region::Pal);
+        assert_eq!(emu.clocks_per_frame(), clocks);
+        assert_eq!(emu.target_fps(), 3_546_894.0 / clocks as f64);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn measured_fps_needs_two_frames() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        assert_eq!(emu.measured_fps(), None);
+
+        emu.run();
+        assert_eq!(emu.measured_fps(), None);
+
+        for _ in 0..3 {
+            emu.run();
+        }
+        assert!(emu.measured_fps().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn core_runs_on_another_thread() {
+        // Boxed, as the docs suggest
+        let mut emu = Box::new(init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap());
+        emu.set_sync_callback(Box::new(|_| {}));
+        let emu = std::thread::spawn(move || {
+            emu.run();
+            emu
+        })
+        .join()
+        .unwrap();
+        assert_eq!(emu.frame_count(), 1);
+    }
+
+    #[test]
+    fn frames_yields_each_frame_run() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        let frames: Vec<_> = emu.frames().take(3).collect();
+        assert_eq!(emu.frame_count(), 3);
+
+        let mut expected = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        for frame in &frames {
+            expected.run();
+            assert_eq!(frame, expected.frame_indices());
+        }
+    }
+
+    #[test]
+    fn interrupts_vector_through_the_cartridge() {
+        let mut asm = Asm::new(0xf000);
+        asm.jmp_here();
+        let nmi = asm.here();
+        asm.lda_imm(1).sta_zp(0x80).cli().jmp_here();
+        let irq = asm.here();
+        asm.lda_imm(2).sta_zp(0x81).jmp_here();
+        let mut rom = asm.rom();
+        rom[0xffa..0xffc].copy_from_slice(&nmi.to_le_bytes());
+        rom[0xffe..0x1000].copy_from_slice(&irq.to_le_bytes());
+        let mut emu = init_emulator_from_bytes(rom).unwrap();
+
+        // The I flag is set at reset, which an NMI ignores
+        emu.trigger_nmi();
+        for _ in 0..4 {
+            emu.step_instruction();
+        }
+        assert_eq!(emu.peek(0x80), 1);
+
+        emu.set_irq(true);
+        for _ in 0..4 {
+            emu.step_instruction();
+        }
+        assert_eq!(emu.peek(0x81), 2);
+    }
+
+    #[test]
+    fn jammed_cpu_leaves_the_rest_running() {
+        let mut asm = Asm::new(0xf000);
+        asm.lda_imm(0x02).sta_zp(0x00).nop().jam();
+        let mut emu = init_emulator_from_bytes(asm.rom()).unwrap();
+
+        // Jammed with VSYNC on, frames still come to an end
+        emu.run();
+        emu.run();
+        assert!(emu.cpu_jammed());
+        let cycles = emu.total_cycles();
+        emu.step_instruction();
+        assert_eq!(emu.run_until_cycle(cycles + 100), cycles);
+        assert!(emu.run_instructions(3).is_empty());
+
+        let clock = emu.beam_position();
+        emu.advance(10);
+        assert_ne!(emu.beam_position(), clock);
+
+        emu.power_cycle();
+        assert!(!emu.cpu_jammed());
+        assert_eq!(emu.run_instructions(3).len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Genesis pad port 2 out of range")]
+    fn out_of_range_port_panics() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.set_genesis_button(2, GenesisButton::A, true);
+    }
+
+    #[test]
+    fn step_instruction_counts_instructions() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        assert_eq!(emu.instructions_executed(), 0);
+
+        for _ in 0..100 {
+            emu.step_instruction();
+        }
+        assert_eq!(emu.instructions_executed(), 100);
+        assert!(emu.total_cycles() >= 200);
+
+        emu.power_cycle();
+        assert_eq!(emu.instructions_executed(), 0);
+        assert_eq!(emu.total_cycles(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn render_from_state_matches_original_frame() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        for _ in 0..60 {
+            emu.run();
+        }
+        let state = emu.save_state();
+        emu.run();
+        let expected = *emu.frame_pixels();
+
+        let mut fresh = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        assert_eq!(fresh.render_from_state(&state), &expected);
+
+        // The fresh core is back at power-on, and carries on from there
+        assert_eq!(fresh.instructions_executed(), 0);
+        assert_eq!(fresh.measured_fps(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn render_from_state_reports_nothing() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        emu.set_sync_callback(Box::new(move |event| sink.lock().unwrap().push(event)));
+        let state = emu.save_state();
+        emu.render_from_state(&state);
+        assert!(events.lock().unwrap().is_empty());
+        emu.run();
+        assert!(!events.lock().unwrap().is_empty());
+
+        // Reads RESP0, then turns VSYNC on to end the frame
+        let mut asm = Asm::new(0xf000);
+        asm.lda_zp(0x10).lda_imm(0x02).sta_zp(0x00).jmp_here();
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        let mut emu = init_emulator_from_bytes(asm.rom()).unwrap();
+        emu.set_diagnostics_callback(Box::new(move |warning| sink.lock().unwrap().push(warning)));
+        emu.set_register_warnings(true);
+        let state = emu.save_state();
+        emu.render_from_state(&state);
+        assert!(warnings.lock().unwrap().is_empty());
+        emu.step_instruction();
+        assert_eq!(warnings.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn frame_rgb24_matches_rgb_image() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        for _ in 0..60 {
+            emu.run();
+        }
+
+        let rgb = emu.frame_rgb24();
+        assert_eq!(rgb.len(), 160 * 192 * 3);
+        assert_eq!(rgb, emu.to_rgb_image().into_raw());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn save_frame_png_writes_frame() {
+        let mut emu = init_emulator_from_bytes(TEST_ROM.to_vec()).unwrap();
+        for _ in 0..60 {
+            emu.run();
+        }
+
+        let path = std::env::temp_dir().join(format!("frame-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        emu.save_frame_png(path).unwrap();
+        let image = image::open(path).unwrap().into_rgba8();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(image.dimensions(), (160, 192));
+        for (y, row) in emu.frame_pixels().iter().enumerate() {
+            for (x, pixel) in row.iter().enumerate() {
+                assert_eq!(image.get_pixel(x as u32, y as u32), pixel);
+            }
+        }
+
+        assert!(emu.save_frame_png("/nonexistent/frame.png").is_err());
+    }
+
+    #[test]
+    fn smoke_test_is_deterministic() {
+        let hash = smoke_test(TEST_ROM, 60).unwrap();
+        assert_eq!(smoke_test(TEST_ROM, 60).unwrap(), hash);
+
+        // An earlier frame looks different
+        assert_ne!(smoke_test(TEST_ROM, 1).unwrap(), hash);
+        assert!(smoke_test(&TEST_ROM[..100], 1).is_err());
+    }
+
+    #[test]
+    fn wsync_during_vblank_keeps_line_count() {
+        #[rustfmt::skip]
+        let program = [
+            0xa9, 0x02,       // F000  LDA #2
+            0x85, 0x01,       // F002  STA VBLANK
+            0x85, 0x00,       // F004  STA VSYNC
+            0x85, 0x02,       // F006  STA WSYNC
+            0x85, 0x02,       // F008  STA WSYNC
+            0x85, 0x02,       // F00A  STA WSYNC
+            0xa9, 0x00,       // F00C  LDA #0
+            0x85, 0x00,       // F00E  STA VSYNC
+            0xa2, 0x25,       // F010  LDX #37
+            0x85, 0x02,       // F012  STA WSYNC
+            0xca,             // F014  DEX
+            0xd0, 0xfb,       // F015  BNE F012
+            0x85, 0x01,       // F017  STA VBLANK
+            0xa2, 0xc0,       // F019  LDX #192
+            0x86, 0x09,       // F01B  STX COLUBK
+            0x85, 0x02,       // F01D  STA WSYNC
+            0xca,             // F01F  DEX
+            0xd0, 0xf9,       // F020  BNE F01B
+            0xa9, 0x02,       // F022  LDA #2
+            0x85, 0x01,       // F024  STA VBLANK
+            0xa2, 0x1e,       // F026  LDX #30
+            0x85, 0x02,       // F028  STA WSYNC
+            0xca,             // F02A  DEX
+            0xd0, 0xfb,       // F02B  BNE F028
+            0x4c, 0x00, 0xf0, // F02D  JMP F000
+        ];
+        let mut emu = init_emulator_from_bytes(rom_with_program(&program)).unwrap();
+
+        for _ in 0..3 {
+            emu.run();
+        }
+
+        // Each visible line is drawn in the background color written at its start, counting down
+        // from 192, so the first row shows the first line after VBLANK
+        for (i, row) in emu.frame_indices().iter().enumerate() {
+            let expected = (192 - i as u8) >> 1;
+            assert!(row.iter().all(|&index| index == expected), "row {}", i);
+        }
+    }
+
+    #[test]
+    fn tia_registers_touched_last_frame_lists_writes() {
+        #[rustfmt::skip]
+        let program = [
+            0xa9, 0x02,       // F000  LDA #2
+            0x85, 0x00,       // F002  STA VSYNC
+            0x85, 0x02,       // F004  STA WSYNC
+            0x85, 0x02,       // F006  STA WSYNC
+            0x85, 0x02,       // F008  STA WSYNC
+            0xa9, 0x00,       // F00A  LDA #0
+            0x85, 0x00,       // F00C  STA VSYNC
+            0xa2, 0xc0,       // F00E  LDX #192
+            0x86, 0x09,       // F010  STX COLUBK
+            0x86, 0x0d,       // F012  STX PF0
+            0x85, 0x02,       // F014  STA WSYNC
+            0xca,             // F016  DEX
+            0xd0, 0xf7,       // F017  BNE F010
+            0x4c, 0x00, 0xf0, // F019  JMP F000
+        ];
+        let mut emu = init_emulator_from_bytes(rom_with_program(&program)).unwrap();
+        assert!(emu.tia_registers_touched_last_frame().is_empty());
+
+        for _ in 0..2 {
+            emu.run();
+        }
+
+        use TiaWriteAddress::*;
+        assert_eq!(
+            emu.tia_registers_touched_last_frame(),
+            [VSYNC, WSYNC, COLUBK, PF0]
+        );
+    }
 }
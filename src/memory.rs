@@ -1,4 +1,5 @@
-use std::error::Error;
+use alloc::{boxed::Box, format};
+use core::{error::Error, fmt};
 
 #[derive(Debug)]
 pub enum Operation {
@@ -35,7 +36,16 @@ impl MemoryMirrors {
             // The TIA chip is addressed by A12=0, A7=0
             a if a & A7 == 0 => match op {
                 Operation::Read => Ok(Self::TiaRead(((address & 0x0f) | 0x30).try_into()?)),
-                Operation::Write => Ok(Self::TiaWrite((address & 0x3f).try_into()?)),
+                // Writes decode A0-A5, which leaves $2D-$3F without a register on every mirror
+                Operation::Write => match TiaWriteAddress::try_from(address & 0x3f) {
+                    Ok(register) => Ok(Self::TiaWrite(register)),
+                    Err(_) => Err(format!(
+                        "Invalid TIA Write address: {:X}, no register at {:X}",
+                        address,
+                        address & 0x3f
+                    )
+                    .into()),
+                },
             },
 
             _ => Err(format!("Invalid address: {:X}", address).into()),
@@ -43,7 +53,98 @@ impl MemoryMirrors {
     }
 }
 
-#[derive(Debug)]
+/// What a CPU address maps to, as returned by `EmulatorCore::describe_address`.
+///
+/// The chips only decode some of the address lines, so each of their registers and bytes appears
+/// at many addresses. `address` is the lowest of the addresses that map to the same place, e.g.
+/// the TIA ignores A6, so `$40` describes as `$00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegion {
+    /// TIA registers. Reads only decode A0-A3 and writes A0-A5, so the same address reaches a
+    /// different register when read than when written, and either may be unused.
+    Tia {
+        address: u16,
+        read: Option<TiaReadAddress>,
+        write: Option<TiaWriteAddress>,
+    },
+    /// One of the RIOT's 128 bytes of RAM, by its offset from `$80`.
+    Ram { address: u16, offset: u8 },
+    /// A RIOT I/O or timer register.
+    Riot { address: u16, register: PiaAddress },
+    /// A byte of the cartridge ROM, by its offset into the ROM.
+    Cartridge { address: u16, offset: usize },
+    /// An address no chip responds to.
+    Unmapped,
+}
+
+impl MemoryRegion {
+    // Describes `address` as seen by the 6507, on a cartridge of `rom_size` bytes
+    pub(crate) fn describe(address: u16, rom_size: usize) -> Self {
+        let address = address & 0x1fff;
+        let tia_read = match MemoryMirrors::from(address, Operation::Read) {
+            Ok(MemoryMirrors::TiaRead(register)) => Some(register),
+            _ => None,
+        };
+
+        match MemoryMirrors::from(address, Operation::Write) {
+            Ok(MemoryMirrors::Cartridge(offset)) => {
+                let offset = offset & (rom_size - 1);
+                Self::Cartridge {
+                    address: 0x1000 | offset as u16,
+                    offset,
+                }
+            }
+            Ok(MemoryMirrors::PiaIO(register)) => Self::Riot {
+                address: address & 0x2ff,
+                register,
+            },
+            Ok(MemoryMirrors::PiaRam(PiaAddress::RAM(offset))) => Self::Ram {
+                address: 0x80 | offset as u16,
+                offset: offset as u8,
+            },
+            Ok(MemoryMirrors::TiaWrite(register)) => Self::Tia {
+                address: address & 0x3f,
+                read: tia_read,
+                write: Some(register),
+            },
+            _ if tia_read.is_some() => Self::Tia {
+                address: address & 0x3f,
+                read: tia_read,
+                write: None,
+            },
+            _ => Self::Unmapped,
+        }
+    }
+}
+
+impl fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Tia {
+                address,
+                read,
+                write,
+            } => {
+                write!(f, "TIA ${:02X}", address)?;
+                if let Some(read) = read {
+                    write!(f, ", {} on read", read)?;
+                }
+                if let Some(write) = write {
+                    write!(f, ", {} on write", write)?;
+                }
+                Ok(())
+            }
+            Self::Ram { address, offset } => write!(f, "RAM ${:02X} (byte {})", address, offset),
+            Self::Riot { address, register } => write!(f, "RIOT ${:04X}, {}", address, register),
+            Self::Cartridge { address, offset } => {
+                write!(f, "cartridge ${:04X} (ROM offset ${:03X})", address, offset)
+            }
+            Self::Unmapped => write!(f, "unmapped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 // Enum representing TIA read addresses
 pub enum TiaReadAddress {
     CXM0P,  // 30 - 11...... Read collision M0-P1, M0-P0 (Bit 7, 6)
@@ -62,6 +163,18 @@ pub enum TiaReadAddress {
     INPT5,  // 3D - 1....... Read input
 }
 
+impl TiaReadAddress {
+    // The bits the TIA actually drives when this register is read. The remaining bits are left
+    // floating on the data bus.
+    pub fn driven_bits(&self) -> u8 {
+        use TiaReadAddress::*;
+        match self {
+            CXBLPF | INPT0 | INPT1 | INPT2 | INPT3 | INPT4 | INPT5 => 0b1000_0000,
+            _ => 0b1100_0000,
+        }
+    }
+}
+
 impl TryFrom<u16> for TiaReadAddress {
     type Error = Box<dyn Error>;
     fn try_from(address: u16) -> Result<Self, Self::Error> {
@@ -86,8 +199,37 @@ impl TryFrom<u16> for TiaReadAddress {
     }
 }
 
-#[derive(Debug)]
-// Enum representing TIA write addresses
+impl TiaReadAddress {
+    /// The register's mnemonic, such as `INPT4`.
+    pub fn name(&self) -> &'static str {
+        use TiaReadAddress::*;
+        match self {
+            CXM0P => "CXM0P",
+            CXM1P => "CXM1P",
+            CXP0FB => "CXP0FB",
+            CXP1FB => "CXP1FB",
+            CXM0FB => "CXM0FB",
+            CXM1FB => "CXM1FB",
+            CXBLPF => "CXBLPF",
+            CXPPMM => "CXPPMM",
+            INPT0 => "INPT0",
+            INPT1 => "INPT1",
+            INPT2 => "INPT2",
+            INPT3 => "INPT3",
+            INPT4 => "INPT4",
+            INPT5 => "INPT5",
+        }
+    }
+}
+
+impl fmt::Display for TiaReadAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Enum representing TIA write addresses, in address order
 pub enum TiaWriteAddress {
     VSYNC,  // 00 - ......1. Vertical sync set-clear
     VBLANK, // 01 - 11....1. Vertical blank set-clear
@@ -190,7 +332,67 @@ impl TryFrom<u16> for TiaWriteAddress {
     }
 }
 
-#[derive(Debug)]
+impl TiaWriteAddress {
+    /// The register's mnemonic, such as `WSYNC`.
+    pub fn name(&self) -> &'static str {
+        use TiaWriteAddress::*;
+        match self {
+            VSYNC => "VSYNC",
+            VBLANK => "VBLANK",
+            WSYNC => "WSYNC",
+            RSYNC => "RSYNC",
+            NUSIZ0 => "NUSIZ0",
+            NUSIZ1 => "NUSIZ1",
+            COLUP0 => "COLUP0",
+            COLUP1 => "COLUP1",
+            COLUPF => "COLUPF",
+            COLUBK => "COLUBK",
+            CTRLPF => "CTRLPF",
+            REFP0 => "REFP0",
+            REFP1 => "REFP1",
+            PF0 => "PF0",
+            PF1 => "PF1",
+            PF2 => "PF2",
+            RESP0 => "RESP0",
+            RESP1 => "RESP1",
+            RESM0 => "RESM0",
+            RESM1 => "RESM1",
+            RESBL => "RESBL",
+            AUDC0 => "AUDC0",
+            AUDC1 => "AUDC1",
+            AUDF0 => "AUDF0",
+            AUDF1 => "AUDF1",
+            AUDV0 => "AUDV0",
+            AUDV1 => "AUDV1",
+            GRP0 => "GRP0",
+            GRP1 => "GRP1",
+            ENAM0 => "ENAM0",
+            ENAM1 => "ENAM1",
+            ENABL => "ENABL",
+            HMP0 => "HMP0",
+            HMP1 => "HMP1",
+            HMM0 => "HMM0",
+            HMM1 => "HMM1",
+            HMBL => "HMBL",
+            VDELP0 => "VDELP0",
+            VDELP1 => "VDELP1",
+            VDELBL => "VDELBL",
+            RESMP0 => "RESMP0",
+            RESMP1 => "RESMP1",
+            HMOVE => "HMOVE",
+            HMCLR => "HMCLR",
+            CXCLR => "CXCLR",
+        }
+    }
+}
+
+impl fmt::Display for TiaWriteAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 // Enum representing PIA 6532 addresses for read and write operations
 pub enum PiaAddress {
     RAM(usize), // 00..=7F - 128 bytes RAM (in PIA chip) for variables and stack
@@ -198,8 +400,8 @@ pub enum PiaAddress {
     SWACNT,     // 0281 - Port A DDR, 0=input, 1=output (read or write)
     SWCHB,      // 0282 - Port B; console switches (read only)
     SWBCNT,     // 0283 - Port B DDR (hardwired as input) (read only)
-    INTIM,      // 0284 - Timer output (read only)
-    INSTAT,     // 0285 - Timer Status (read only, undocumented)
+    INTIM,      // 0284 - Timer output (read), PA7 falling edge detect (write)
+    INSTAT,     // 0285 - Timer Status (read, undocumented), PA7 rising edge detect (write)
     TIM1T,      // 0294 - Set 1 clock interval (838 nsec/interval) (read or write)
     TIM8T,      // 0295 - Set 8 clock interval (6.7 usec/interval) (read or write)
     TIM64T,     // 0296 - Set 64 clock interval (53.6 usec/interval) (read or write)
@@ -215,8 +417,9 @@ impl TryFrom<u16> for PiaAddress {
             0x0281 => Ok(Self::SWACNT),
             0x0282 => Ok(Self::SWCHB),
             0x0283 => Ok(Self::SWBCNT),
-            0x0284 => Ok(Self::INTIM),
-            0x0285 => Ok(Self::INSTAT),
+            // A1 only enables the PA7 interrupt, and there's no IRQ line to raise on the 2600
+            0x0284 | 0x0286 => Ok(Self::INTIM),
+            0x0285 | 0x0287 => Ok(Self::INSTAT),
             0x0294 => Ok(Self::TIM1T),
             0x0295 => Ok(Self::TIM8T),
             0x0296 => Ok(Self::TIM64T),
@@ -225,3 +428,157 @@ impl TryFrom<u16> for PiaAddress {
         }
     }
 }
+
+impl PiaAddress {
+    /// The register's mnemonic, such as `SWCHA`. All of RAM is named `RAM`.
+    pub fn name(&self) -> &'static str {
+        use PiaAddress::*;
+        match self {
+            RAM(_) => "RAM",
+            SWCHA => "SWCHA",
+            SWACNT => "SWACNT",
+            SWCHB => "SWCHB",
+            SWBCNT => "SWBCNT",
+            INTIM => "INTIM",
+            INSTAT => "INSTAT",
+            TIM1T => "TIM1T",
+            TIM8T => "TIM8T",
+            TIM64T => "TIM64T",
+            T1024T => "T1024T",
+        }
+    }
+}
+
+impl fmt::Display for PiaAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_are_named_by_mnemonic() {
+        for address in 0x00..=0x2c {
+            let register = TiaWriteAddress::try_from(address).unwrap();
+            assert_eq!(register.name(), format!("{:?}", register));
+        }
+        for address in 0x30..=0x3d {
+            let register = TiaReadAddress::try_from(address).unwrap();
+            assert_eq!(register.name(), format!("{:?}", register));
+        }
+        for address in [
+            0x280, 0x281, 0x282, 0x283, 0x284, 0x285, 0x294, 0x295, 0x296, 0x297,
+        ] {
+            let register = PiaAddress::try_from(address).unwrap();
+            assert_eq!(register.name(), format!("{:?}", register));
+        }
+
+        assert_eq!(TiaWriteAddress::WSYNC.to_string(), "WSYNC");
+        assert_eq!(PiaAddress::RAM(0x12).to_string(), "RAM");
+    }
+
+    #[test]
+    fn tia_writes_resolve_on_every_mirror() {
+        // The TIA only sees A0-A5 on writes, the other lines just have to keep A12 and A7 low
+        let mirrors = [0x0000, 0x0040, 0x0100, 0x0240, 0x0a00, 0x0f40];
+        for offset in 0x00..=0x2c {
+            let register = TiaWriteAddress::try_from(offset).unwrap();
+            for mirror in mirrors {
+                match MemoryMirrors::from(mirror | offset, Operation::Write) {
+                    Ok(MemoryMirrors::TiaWrite(written)) => assert_eq!(written, register),
+                    other => panic!("{:X} resolved to {:?}", mirror | offset, other),
+                }
+            }
+        }
+
+        for offset in 0x2d..=0x3f {
+            for mirror in mirrors {
+                let error = MemoryMirrors::from(mirror | offset, Operation::Write).unwrap_err();
+                assert_eq!(
+                    error.to_string(),
+                    format!(
+                        "Invalid TIA Write address: {:X}, no register at {:X}",
+                        mirror | offset,
+                        offset
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tia_reads_resolve_on_every_mirror() {
+        // Reads only decode A0-A3, so each register repeats every 16 bytes as well
+        let mirrors = [
+            0x0000, 0x0010, 0x0020, 0x0030, 0x0040, 0x0070, 0x0100, 0x0230, 0x0a50, 0x0f70,
+        ];
+        for offset in 0x0..=0xd {
+            let register = TiaReadAddress::try_from(0x30 | offset).unwrap();
+            for mirror in mirrors {
+                match MemoryMirrors::from(mirror | offset, Operation::Read) {
+                    Ok(MemoryMirrors::TiaRead(read)) => assert_eq!(read, register),
+                    other => panic!("{:X} resolved to {:?}", mirror | offset, other),
+                }
+            }
+        }
+
+        // The last two of every 16 have no register
+        for offset in [0xe, 0xf] {
+            for mirror in mirrors {
+                assert!(MemoryMirrors::from(mirror | offset, Operation::Read).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn mirrors_describe_as_their_lowest_address() {
+        let vsync = MemoryRegion::Tia {
+            address: 0x00,
+            read: Some(TiaReadAddress::CXM0P),
+            write: Some(TiaWriteAddress::VSYNC),
+        };
+        for address in [0x0000, 0x0040, 0x0100, 0x0a00, 0xe000] {
+            assert_eq!(MemoryRegion::describe(address, 4096), vsync);
+        }
+        assert_eq!(vsync.to_string(), "TIA $00, CXM0P on read, VSYNC on write");
+
+        // Reads decode fewer lines than writes, so they stay mapped past the write registers
+        assert_eq!(
+            MemoryRegion::describe(0x003c, 4096),
+            MemoryRegion::Tia {
+                address: 0x3c,
+                read: Some(TiaReadAddress::INPT4),
+                write: None,
+            }
+        );
+        assert_eq!(MemoryRegion::describe(0x003e, 4096), MemoryRegion::Unmapped);
+
+        assert_eq!(
+            MemoryRegion::describe(0x01ff, 4096),
+            MemoryRegion::Ram {
+                address: 0xff,
+                offset: 0x7f,
+            }
+        );
+        assert_eq!(
+            MemoryRegion::describe(0x0a86, 4096),
+            MemoryRegion::Riot {
+                address: 0x0286,
+                register: PiaAddress::INTIM,
+            }
+        );
+        assert_eq!(MemoryRegion::describe(0x0288, 4096), MemoryRegion::Unmapped);
+
+        // A 2K cartridge appears twice, and the 6507 has no A13-A15
+        assert_eq!(
+            MemoryRegion::describe(0xfffc, 2048),
+            MemoryRegion::Cartridge {
+                address: 0x17fc,
+                offset: 0x7fc,
+            }
+        );
+    }
+}
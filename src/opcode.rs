@@ -1,8 +1,9 @@
 // https://www.masswerk.at/6502/6502_instruction_set.html
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Copy, Clone, Debug)]
-pub(crate) enum Instruction {
-    None, // No operation
+/// A 6502 instruction, including the unofficial ones the CPU runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    None, // Opcode the CPU doesn't run
 
     // Arithmetic Instructions
     ADC, // Add with Carry
@@ -125,7 +126,84 @@ pub(crate) enum Instruction {
     TYA, // Transfer Y to Accumulator
 }
 
-#[derive(Copy, Clone, Debug)]
+impl Instruction {
+    /// The instruction's assembler mnemonic, e.g. `"LDA"`. `Instruction::None` has none, and
+    /// returns `"???"`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::None => "???",
+            Instruction::ADC => "ADC",
+            Instruction::ANC => "ANC",
+            Instruction::AND => "AND",
+            Instruction::ASL => "ASL",
+            Instruction::BCC => "BCC",
+            Instruction::BCS => "BCS",
+            Instruction::BEQ => "BEQ",
+            Instruction::BIT => "BIT",
+            Instruction::BMI => "BMI",
+            Instruction::BNE => "BNE",
+            Instruction::BPL => "BPL",
+            Instruction::BRK => "BRK",
+            Instruction::BVC => "BVC",
+            Instruction::BVS => "BVS",
+            Instruction::CLC => "CLC",
+            Instruction::CLD => "CLD",
+            Instruction::CLI => "CLI",
+            Instruction::CLV => "CLV",
+            Instruction::CMP => "CMP",
+            Instruction::CPX => "CPX",
+            Instruction::CPY => "CPY",
+            Instruction::DCP => "DCP",
+            Instruction::DEC => "DEC",
+            Instruction::DEX => "DEX",
+            Instruction::DEY => "DEY",
+            Instruction::EOR => "EOR",
+            Instruction::INC => "INC",
+            Instruction::INX => "INX",
+            Instruction::INY => "INY",
+            Instruction::ISB => "ISB",
+            Instruction::JAM => "JAM",
+            Instruction::JMP => "JMP",
+            Instruction::JSR => "JSR",
+            Instruction::LAX => "LAX",
+            Instruction::LDA => "LDA",
+            Instruction::LDX => "LDX",
+            Instruction::LDY => "LDY",
+            Instruction::LSR => "LSR",
+            Instruction::NOP => "NOP",
+            Instruction::ORA => "ORA",
+            Instruction::PHA => "PHA",
+            Instruction::PHP => "PHP",
+            Instruction::PLA => "PLA",
+            Instruction::PLP => "PLP",
+            Instruction::RLA => "RLA",
+            Instruction::ROL => "ROL",
+            Instruction::ROR => "ROR",
+            Instruction::RRA => "RRA",
+            Instruction::RTI => "RTI",
+            Instruction::RTS => "RTS",
+            Instruction::SAX => "SAX",
+            Instruction::SBC => "SBC",
+            Instruction::SEC => "SEC",
+            Instruction::SED => "SED",
+            Instruction::SEI => "SEI",
+            Instruction::SLO => "SLO",
+            Instruction::SRE => "SRE",
+            Instruction::STA => "STA",
+            Instruction::STX => "STX",
+            Instruction::STY => "STY",
+            Instruction::TAX => "TAX",
+            Instruction::TAY => "TAY",
+            Instruction::TSX => "TSX",
+            Instruction::TXA => "TXA",
+            Instruction::TXS => "TXS",
+            Instruction::TYA => "TYA",
+        }
+    }
+}
+
+/// How an instruction finds its operand. `AddressingMode::None` goes with `Instruction::None`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum AddressingMode {
     None,
     Immediate,
@@ -144,6 +222,11 @@ pub enum AddressingMode {
 }
 
 impl AddressingMode {
+    /// The number of bytes an instruction takes up with this mode, the opcode included.
+    ///
+    /// # Panics
+    ///
+    /// If called on `AddressingMode::None`.
     pub fn n_bytes(&self) -> usize {
         match *self {
             AddressingMode::Implied | AddressingMode::Accumulator => 1,
@@ -166,15 +249,40 @@ impl AddressingMode {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub(crate) struct Opcode(
+/// An entry of `OPCODES`: what an opcode does, and how many cycles it takes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Opcode(
     pub(crate) Instruction,
     pub(crate) AddressingMode,
     pub(crate) u64, // number of cycles
     pub(crate) u64,
 ); // number of extra cycles, if a page boundary is crossed
 
-pub(crate) const OPCODES: [Opcode; 256] = [
+impl Opcode {
+    pub fn instruction(&self) -> Instruction {
+        self.0
+    }
+
+    pub fn addressing_mode(&self) -> AddressingMode {
+        self.1
+    }
+
+    /// The number of cycles the instruction takes, before any extra ones. Branches take one more
+    /// when taken, and another one if that crosses a page.
+    pub fn cycles(&self) -> u64 {
+        self.2
+    }
+
+    /// The number of extra cycles it takes when indexing crosses a page boundary. Branches list 1
+    /// here, for the cycle they take when taken.
+    pub fn page_cross_cycles(&self) -> u64 {
+        self.3
+    }
+}
+
+/// What each of the 256 opcodes does, indexed by opcode. Opcodes the CPU doesn't run are
+/// `Instruction::None`.
+pub const OPCODES: [Opcode; 256] = [
     // 0x00
     Opcode(Instruction::BRK, AddressingMode::Implied, 7, 0),
     Opcode(Instruction::ORA, AddressingMode::IndexedIndirect, 6, 0),
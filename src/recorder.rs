@@ -0,0 +1,77 @@
+use gif::{Encoder, Frame, Repeat};
+use image::{error::EncodingError, ImageError, ImageFormat, ImageResult, Rgba};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+// GIF frame delays are in hundredths of a second, so 60 frames per second can't be represented.
+// 2/100 is the closest delay viewers honour, many of them slow down anything shorter.
+const FRAME_DELAY_MS: u32 = 20;
+
+/// Writes frames, such as `EmulatorCore::frame_pixels`, to an animated GIF as they are pushed.
+/// The file is complete once the recorder is `finish`ed. Dropping it completes the file too, but
+/// any error writing the end of it is lost.
+///
+/// The NTSC palette only has 128 colors, so frames are stored without any loss. They play back at
+/// 50 frames per second, a little slower than the console.
+pub struct FrameRecorder {
+    encoder: Encoder<BufWriter<File>>,
+}
+
+impl FrameRecorder {
+    pub fn new(path: &str) -> ImageResult<Self> {
+        let file = File::create(path)?;
+        let mut encoder = Encoder::new(BufWriter::new(file), 160, 192, &[]).map_err(gif_error)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+
+        Ok(Self { encoder })
+    }
+
+    pub fn push(&mut self, frame: &[[Rgba<u8>; 160]; 192]) -> ImageResult<()> {
+        let mut pixels: Vec<u8> = frame.iter().flatten().flat_map(|pixel| pixel.0).collect();
+        let mut frame = Frame::from_rgba_speed(160, 192, &mut pixels, 1);
+        frame.delay = (FRAME_DELAY_MS / 10) as u16;
+        self.encoder.write_frame(&frame).map_err(gif_error)
+    }
+
+    /// Ends the animation, and writes out what is still buffered.
+    pub fn finish(self) -> ImageResult<()> {
+        self.encoder.into_inner()?.flush()?;
+        Ok(())
+    }
+}
+
+fn gif_error(e: gif::EncodingError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(ImageFormat::Gif.into(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    #[test]
+    fn frames_are_written_in_order() {
+        let path = std::env::temp_dir().join(format!("recorder-{}.gif", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let colors = [Rgba([0, 0, 0, 0xff]), Rgba([0xfc, 0xe0, 0x8c, 0xff])];
+        let mut recorder = FrameRecorder::new(path).unwrap();
+        for color in colors {
+            recorder.push(&[[color; 160]; 192]).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let decoder = GifDecoder::new(File::open(path).unwrap()).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(frames.len(), colors.len());
+        for (frame, color) in frames.iter().zip(colors) {
+            assert_eq!(frame.buffer().dimensions(), (160, 192));
+            assert_eq!(*frame.buffer().get_pixel(80, 100), color);
+            assert_eq!(frame.delay().numer_denom_ms(), (FRAME_DELAY_MS, 1));
+        }
+    }
+}
@@ -0,0 +1,26 @@
+/// The TV standard the console is built for. It determines the master (color) clock and how many
+/// scanlines make up a frame. Only the timing figures depend on it, the NTSC palette is always
+/// used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    /// TIA color clock frequency in Hz
+    pub fn master_clock(&self) -> f64 {
+        match self {
+            Region::Ntsc => 3_579_545.0,
+            Region::Pal => 3_546_894.0,
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> usize {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal => 312,
+        }
+    }
+}
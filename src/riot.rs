@@ -1,7 +1,70 @@
-use crate::memory::PiaAddress;
+use crate::{
+    controller::{Controller, Quadrature},
+    memory::PiaAddress,
+};
+
+// INSTAT bits
+const TIMER_FLAG: u8 = 0b1000_0000;
+const PA7_FLAG: u8 = 0b0100_0000;
+
+// How many clocks a mouse or driving controller takes per quadrature step, one per scanline
+const ENCODER_STEP_CLOCKS: usize = 76;
+
+/// A snapshot of the RIOT timer, read without the side effects of reading INTIM/INSTAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerState {
+    /// The timer's count, as INTIM reads.
+    pub intim: u8,
+    /// Bit 7 is set when the timer underflows, until INTIM is read or a timer written. Bit 6 is
+    /// set by an active PA7 edge, until INSTAT is read. The other bits read as 0.
+    pub instat: u8,
+    /// The interval selected by the last TIMxT write (1, 8, 64 or 1024), or 0 if none was written.
+    /// It is kept after an underflow, even though the timer then counts down once per clock.
+    pub resolution: usize,
+}
+
+impl TimerState {
+    /// Whether the timer has underflowed since INTIM was last read or a timer written, INSTAT
+    /// bit 7.
+    pub fn underflowed(&self) -> bool {
+        (self.instat & TIMER_FLAG) != 0
+    }
+}
+
+/// How the RIOT's 128 bytes of RAM are filled at power on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RamInit {
+    /// All zeroes.
+    #[default]
+    Zeroed,
+    /// Pseudo-random bytes, always the same ones for the same seed. Real hardware powers up with
+    /// indeterminate RAM, which some games use to seed their random numbers.
+    Random(u64),
+}
+
+impl RamInit {
+    fn fill(&self, ram: &mut [u8]) {
+        match *self {
+            RamInit::Zeroed => ram.fill(0),
+            RamInit::Random(seed) => {
+                // SplitMix64, which is good enough for this and needs no dependencies
+                let mut state = seed;
+                for chunk in ram.chunks_mut(8) {
+                    state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                    z ^= z >> 31;
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                }
+            }
+        }
+    }
+}
 
 #[allow(clippy::upper_case_acronyms)]
 // The RIOT (RAM/IO/Timer) chip. Also known as the PIA. It's a MOS 6532 chip.
+#[derive(Clone)]
 pub(crate) struct RIOT {
     ram: [u8; 128],
 
@@ -17,8 +80,23 @@ pub(crate) struct RIOT {
     port_a: u8,
     port_b: u8,
 
+    // Whether a rising edge on PA7 sets the PA7 flag, rather than a falling one
+    pa7_rising_edge: bool,
+
+    // What's plugged into the left and right ports, the horizontal and vertical encoders of the
+    // mouse plugged into either, and the wheels of the driving controllers plugged into each
+    controllers: [Controller; 2],
+    mouse_x: Quadrature,
+    mouse_y: Quadrature,
+    wheels: [Quadrature; 2],
+    encoder_clocks: usize,
+
     resolution: usize,
+    // The interval written through TIMxT, which `resolution` drops back from after an underflow
+    interval: usize,
     cycle_count: usize,
+
+    ram_init: RamInit,
 }
 
 impl Default for RIOT {
@@ -40,8 +118,17 @@ impl Default for RIOT {
 
             port_a: 0,
             port_b,
+            pa7_rising_edge: false,
+            controllers: [Controller::Joystick; 2],
+            mouse_x: Quadrature::default(),
+            mouse_y: Quadrature::default(),
+            wheels: [Quadrature::default(); 2],
+            encoder_clocks: 0,
             resolution: 0,
+            interval: 0,
             cycle_count: 0,
+
+            ram_init: RamInit::Zeroed,
         }
     }
 }
@@ -51,9 +138,32 @@ impl RIOT {
         Self::default()
     }
 
+    pub fn new_with_ram_init(init: RamInit) -> Self {
+        let mut riot = Self {
+            ram_init: init,
+            ..Self::new()
+        };
+        init.fill(&mut riot.ram);
+        riot
+    }
+
     //
     // Console switches
     //
+    // Returns the chip to its power-on state. The console switches and joysticks are physical, so
+    // they stay in whatever position they are in.
+    pub fn power_cycle(&mut self) {
+        *self = Self {
+            port_a: self.port_a,
+            port_b: self.port_b,
+            controllers: self.controllers,
+            mouse_x: self.mouse_x,
+            mouse_y: self.mouse_y,
+            wheels: self.wheels,
+            ..Self::new_with_ram_init(self.ram_init)
+        };
+    }
+
     pub fn color(&mut self) {
         if (self.port_b & 0b0000_1000) != 0 {
             self.port_b &= 0b1111_0111;
@@ -62,6 +172,29 @@ impl RIOT {
         }
     }
 
+    // Sets the P0 (player 0) or P1 (player 1) difficulty switch. Hard is the "A" (pro) position.
+    pub fn set_difficulty(&mut self, player: u8, hard: bool) {
+        let mask = match player {
+            0 => 0b0100_0000,
+            1 => 0b1000_0000,
+            _ => panic!("difficulty switch {} out of range", player),
+        };
+
+        if hard {
+            self.port_b |= mask;
+        } else {
+            self.port_b &= !mask;
+        }
+    }
+
+    pub fn set_tv_type(&mut self, color: bool) {
+        if color {
+            self.port_b |= 0b0000_1000;
+        } else {
+            self.port_b &= 0b1111_0111;
+        }
+    }
+
     pub fn reset(&mut self, pressed: bool) {
         if pressed {
             self.port_b &= 0b1111_1110;
@@ -79,41 +212,128 @@ impl RIOT {
     }
 
     //
-    // Player 0 joystick controls
+    // Joysticks
     //
-    pub fn up(&mut self, pressed: bool) {
-        if pressed {
-            self.port_a &= 0b1110_1111
-        } else {
-            self.port_a |= 0b0001_0000
+    // Sets the directions pressed on the joystick in the left (0) or right (1) port, as a mask of
+    // `Direction` bits. The left joystick's right direction is wired to PA7, the pin the RIOT can
+    // detect edges on.
+    pub fn set_joystick(&mut self, port: usize, pressed: u8) {
+        let shift = 4 * (1 - port);
+        let pa7 = self.pa7();
+        self.port_a = (self.port_a & !(0x0f << shift)) | ((!pressed & 0x0f) << shift);
+        self.detect_pa7_edge(pa7);
+    }
+
+    //
+    // Other controllers
+    //
+    pub fn controller(&self, port: usize) -> Controller {
+        self.controllers[port]
+    }
+
+    pub fn set_controller(&mut self, port: usize, controller: Controller) {
+        let pa7 = self.pa7();
+        self.controllers[port] = controller;
+        self.detect_pa7_edge(pa7);
+    }
+
+    // The level of a Genesis pad's select line, which is wired to the pin of the port's up
+    // direction. It's pulled high unless the game drives it as an output.
+    pub fn genesis_select(&self, port: usize) -> bool {
+        let bit = 0b0001_0000 >> (4 * port);
+        (self.swacnt & bit) == 0 || (self.swcha & bit) != 0
+    }
+
+    pub fn mouse_move(&mut self, dx: i8, dy: i8) {
+        self.mouse_x.move_by(dx.into());
+        self.mouse_y.move_by(dy.into());
+    }
+
+    // Turns the wheel of the driving controller in `port`, by `delta` steps of its encoder.
+    // Positive is clockwise.
+    pub fn driving_turn(&mut self, port: usize, delta: i8) {
+        assert!(port < 2, "driving controller port {} out of range", port);
+        self.wheels[port].move_by(delta.into());
+    }
+
+    fn clock_encoders(&mut self) {
+        self.encoder_clocks += 1;
+        if self.encoder_clocks < ENCODER_STEP_CLOCKS {
+            return;
         }
+        self.encoder_clocks = 0;
+
+        let pa7 = self.pa7();
+        self.mouse_x.step();
+        self.mouse_y.step();
+        for wheel in &mut self.wheels {
+            wheel.step();
+        }
+        self.detect_pa7_edge(pa7);
     }
 
-    pub fn down(&mut self, pressed: bool) {
-        if pressed {
-            self.port_a &= 0b1101_1111
-        } else {
-            self.port_a |= 0b0010_0000
+    // The levels the controllers put on port A, the left one on the upper half and the right one
+    // on the lower half
+    fn port_a_input(&self) -> u8 {
+        let mut input = 0;
+        for (port, controller) in self.controllers.iter().enumerate() {
+            let shift = 4 * (1 - port);
+            let nibble = match controller {
+                // The horizontal encoder is wired to up and left, the vertical one to right and down
+                Controller::AmigaMouse => {
+                    let (x, y) = (self.mouse_x.bits(), self.mouse_y.bits());
+                    (x & 1) | (x >> 1) << 2 | (y & 1) << 3 | (y >> 1) << 1
+                }
+                // Left and right aren't connected, so they're pulled high
+                Controller::Driving => 0b1100 | self.wheels[port].bits(),
+                Controller::Joystick | Controller::Genesis | Controller::QuadTari => {
+                    (self.port_a >> shift) & 0x0f
+                }
+            };
+            input |= nibble << shift;
         }
+        input
     }
 
-    pub fn left(&mut self, pressed: bool) {
-        if pressed {
-            self.port_a &= 0b1011_1111
-        } else {
-            self.port_a |= 0b0100_0000
+    // The level of the PA7 pin, which is driven by SWCHA instead of the port when set as an output
+    fn pa7(&self) -> bool {
+        self.peek(PiaAddress::SWCHA) & 0x80 != 0
+    }
+
+    // Sets the PA7 flag in INSTAT if PA7 has moved from `previous` in the selected direction
+    fn detect_pa7_edge(&mut self, previous: bool) {
+        let pa7 = self.pa7();
+        if pa7 != previous && pa7 == self.pa7_rising_edge {
+            self.instat |= PA7_FLAG;
         }
     }
 
-    pub fn right(&mut self, pressed: bool) {
-        if pressed {
-            self.port_a &= 0b0111_1111
-        } else {
-            self.port_a |= 0b1000_0000
+    pub fn timer_resolution(&self) -> usize {
+        self.interval
+    }
+
+    pub fn timer_state(&self) -> TimerState {
+        TimerState {
+            intim: self.intim,
+            instat: self.instat,
+            resolution: self.timer_resolution(),
         }
     }
 
     pub fn clock(&mut self) {
+        if self
+            .controllers
+            .iter()
+            .any(|&c| c == Controller::AmigaMouse || c == Controller::Driving)
+        {
+            self.clock_encoders();
+        }
+
+        // The timer stays dormant until one of the TIMxT registers is first written
+        if self.resolution == 0 {
+            return;
+        }
+
         if self.cycle_count == 0 {
             self.decrement();
         }
@@ -126,6 +346,8 @@ impl RIOT {
     fn init_timer(&mut self, val: u8, resolution: usize) {
         self.intim = val;
         self.resolution = resolution;
+        self.interval = resolution;
+        self.instat &= !TIMER_FLAG;
         self.decrement();
     }
 
@@ -136,7 +358,7 @@ impl RIOT {
         // If we've successfully decremented the timer down to zero, set a flag in the INSTAT
         // register to record this fact.
         if underflowed {
-            self.instat = 0b1100_0000;
+            self.instat |= TIMER_FLAG;
 
             // Once when the timer does underflow, it restarts at FFh, and is then decremented once
             // per clock cycle, regardless of the selected interval.
@@ -149,6 +371,20 @@ impl RIOT {
 
 impl RIOT {
     pub fn read(&mut self, address: PiaAddress) -> u8 {
+        use PiaAddress::*;
+        let val = self.peek(address);
+        match address {
+            // Reading the timer clears the timer interrupt flag
+            INTIM => self.instat &= !TIMER_FLAG,
+            // Reading INSTAT clears the PA7 flag, but leaves the timer flag alone
+            INSTAT => self.instat &= !PA7_FLAG,
+            _ => {}
+        }
+        val
+    }
+
+    // Returns what `read` would, without clearing any flags
+    pub fn peek(&self, address: PiaAddress) -> u8 {
         use PiaAddress::*;
         match address {
             RAM(addr) => self.ram[addr],
@@ -157,15 +393,11 @@ impl RIOT {
                 // being for input, and 1 for output.
                 // So all this faffing about is to enforce this.
                 // This is also the case for SWCHB/SWBCNT.
-                (self.swcha & self.swacnt) | (self.port_a & (self.swacnt ^ 0xff))
+                (self.swcha & self.swacnt) | (self.port_a_input() & (self.swacnt ^ 0xff))
             }
             SWCHB => (self.swchb & self.swbcnt) | (self.port_b & (self.swbcnt ^ 0xff)),
             INTIM => self.intim,
-            INSTAT => {
-                let rv = self.instat;
-                self.instat &= 0b1011_1111;
-                rv
-            }
+            INSTAT => self.instat,
             _ => 0,
         }
     }
@@ -174,13 +406,356 @@ impl RIOT {
         use PiaAddress::*;
         match address {
             RAM(addr) => self.ram[addr] = val,
-            SWACNT => self.swacnt = val,
+            // Only the bits set as outputs in SWACNT are driven onto the port, see `read`
+            SWCHA => {
+                let pa7 = self.pa7();
+                self.swcha = val;
+                self.detect_pa7_edge(pa7);
+            }
+            SWACNT => {
+                let pa7 = self.pa7();
+                self.swacnt = val;
+                self.detect_pa7_edge(pa7);
+            }
+            // The console wires port B as inputs, but SWBCNT can still turn bits into outputs
+            SWCHB => self.swchb = val,
             SWBCNT => self.swbcnt = val,
             TIM1T => self.init_timer(val, 1),
             TIM8T => self.init_timer(val, 8),
             TIM64T => self.init_timer(val, 64),
             T1024T => self.init_timer(val, 1024),
-            _ => {}
+            // Writes to the timer read addresses select the PA7 edge to detect, by A0
+            INTIM => self.pa7_rising_edge = false,
+            INSTAT => self.pa7_rising_edge = true,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::Direction;
+
+    fn clock_n(riot: &mut RIOT, n: usize) {
+        for _ in 0..n {
+            riot.clock();
+        }
+    }
+
+    #[test]
+    fn console_switches() {
+        let mut riot = RIOT::new();
+        riot.reset(false);
+        riot.select(false);
+        assert_eq!(riot.read(PiaAddress::SWCHB), 0b1100_1011);
+
+        riot.set_difficulty(0, false);
+        assert_eq!(riot.read(PiaAddress::SWCHB), 0b1000_1011);
+        riot.set_difficulty(1, false);
+        assert_eq!(riot.read(PiaAddress::SWCHB), 0b0000_1011);
+        riot.set_difficulty(0, true);
+        assert_eq!(riot.read(PiaAddress::SWCHB), 0b0100_1011);
+
+        riot.set_tv_type(false);
+        assert_eq!(riot.read(PiaAddress::SWCHB), 0b0100_0011);
+        riot.set_tv_type(true);
+        assert_eq!(riot.read(PiaAddress::SWCHB), 0b0100_1011);
+    }
+
+    #[test]
+    fn swcha_outputs_are_latched() {
+        let mut riot = RIOT::new();
+        riot.set_joystick(0, Direction::Left.bit());
+
+        riot.write(PiaAddress::SWACNT, 0xf0);
+        riot.write(PiaAddress::SWCHA, 0xa0);
+        assert_eq!(riot.read(PiaAddress::SWCHA) >> 4, 0xa);
+
+        // Bits set as inputs still read the port, the latch is kept for when they become outputs
+        riot.write(PiaAddress::SWACNT, 0x00);
+        assert_eq!(riot.read(PiaAddress::SWCHA) >> 4, 0b1011);
+        riot.write(PiaAddress::SWACNT, 0xf0);
+        assert_eq!(riot.read(PiaAddress::SWCHA) >> 4, 0xa);
+    }
+
+    #[test]
+    fn swchb_writes_only_drive_outputs() {
+        let mut riot = RIOT::new();
+        riot.reset(false);
+        riot.select(false);
+        let switches = riot.read(PiaAddress::SWCHB);
+
+        // With every bit an input, the switches are left as they are
+        riot.write(PiaAddress::SWCHB, 0x00);
+        assert_eq!(riot.read(PiaAddress::SWCHB), switches);
+
+        // Bits 4 and 5 aren't connected to any switch
+        riot.write(PiaAddress::SWBCNT, 0b0011_0000);
+        riot.write(PiaAddress::SWCHB, 0b0011_0000);
+        assert_eq!(riot.read(PiaAddress::SWCHB), switches | 0b0011_0000);
+        riot.write(PiaAddress::SWCHB, 0x00);
+        assert_eq!(riot.read(PiaAddress::SWCHB), switches & !0b0011_0000);
+    }
+
+    #[test]
+    fn pa7_edges_are_latched_until_instat_read() {
+        let mut riot = RIOT::new();
+        riot.set_joystick(0, 0);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, 0);
+
+        // Falling edges are detected by default
+        riot.set_joystick(0, Direction::Right.bit());
+        assert_eq!(riot.peek(PiaAddress::INSTAT) & PA7_FLAG, PA7_FLAG);
+        riot.set_joystick(0, 0);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, PA7_FLAG);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, 0);
+
+        // Rising edges only
+        riot.write(PiaAddress::INSTAT, 0);
+        riot.set_joystick(0, Direction::Right.bit());
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, 0);
+        riot.set_joystick(0, 0);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, PA7_FLAG);
+
+        // PA7 driven as an output
+        riot.write(PiaAddress::SWCHA, 0x00);
+        riot.write(PiaAddress::SWACNT, 0x80);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, 0);
+        riot.write(PiaAddress::SWCHA, 0x80);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, PA7_FLAG);
+
+        // Reading INSTAT doesn't clear the timer flag, and vice versa
+        riot.write(PiaAddress::TIM1T, 0);
+        riot.write(PiaAddress::SWCHA, 0x00);
+        riot.write(PiaAddress::SWCHA, 0x80);
+        riot.read(PiaAddress::INTIM);
+        assert_eq!(riot.read(PiaAddress::INSTAT), PA7_FLAG);
+    }
+
+    #[test]
+    fn mouse_moves_through_gray_code() {
+        let mut riot = RIOT::new();
+        riot.set_controller(0, Controller::AmigaMouse);
+        let swcha_upper = |riot: &RIOT| riot.peek(PiaAddress::SWCHA) & 0xf0;
+
+        // Horizontal steps toggle up (bit 4) and left (bit 6) in turn, one step per scanline
+        riot.mouse_move(4, 0);
+        let mut seen = vec![swcha_upper(&riot)];
+        for _ in 0..5 {
+            clock_n(&mut riot, ENCODER_STEP_CLOCKS);
+            seen.push(swcha_upper(&riot));
+        }
+        assert_eq!(seen, [0x00, 0x10, 0x50, 0x40, 0x00, 0x00]);
+
+        // Moving back retraces them, and vertical steps toggle right (bit 7) and down (bit 5)
+        riot.mouse_move(-1, 2);
+        clock_n(&mut riot, ENCODER_STEP_CLOCKS);
+        assert_eq!(swcha_upper(&riot), 0x40 | 0x80);
+        clock_n(&mut riot, ENCODER_STEP_CLOCKS);
+        assert_eq!(swcha_upper(&riot), 0x40 | 0xa0);
+
+        // The first vertical step raised PA7, which doesn't set the flag for the default falling
+        // edge, but moving back lowers it again
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, 0);
+        riot.mouse_move(0, -2);
+        clock_n(&mut riot, 2 * ENCODER_STEP_CLOCKS);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & PA7_FLAG, PA7_FLAG);
+    }
+
+    #[test]
+    fn driving_wheel_turns_through_gray_code() {
+        let mut riot = RIOT::new();
+        riot.set_controller(1, Controller::Driving);
+        let swcha_lower = |riot: &RIOT| riot.peek(PiaAddress::SWCHA) & 0x0f;
+
+        // The wheel toggles up (bit 0) and down (bit 1), left and right stay high
+        riot.driving_turn(1, 3);
+        let mut seen = vec![swcha_lower(&riot)];
+        for _ in 0..3 {
+            clock_n(&mut riot, ENCODER_STEP_CLOCKS);
+            seen.push(swcha_lower(&riot));
+        }
+        assert_eq!(seen, [0x0c, 0x0d, 0x0f, 0x0e]);
+
+        riot.driving_turn(1, -1);
+        clock_n(&mut riot, ENCODER_STEP_CLOCKS);
+        assert_eq!(swcha_lower(&riot), 0x0f);
+    }
+
+    #[test]
+    fn clock_before_timer_write() {
+        let mut riot = RIOT::new();
+        clock_n(&mut riot, 100_000);
+
+        assert_eq!(riot.read(PiaAddress::INTIM), 0);
+        assert_eq!(riot.read(PiaAddress::INSTAT), 0);
+    }
+
+    #[test]
+    fn timer_counts_down_and_underflows() {
+        let mut riot = RIOT::new();
+        riot.init_timer(5, 64);
+
+        // The timer is decremented once on the write, then once per interval
+        assert_eq!(riot.read(PiaAddress::INTIM), 4);
+        for t in 1..=5 * 64 {
+            riot.clock();
+            assert_eq!(riot.read(PiaAddress::INTIM), 4 - ((t - 1) / 64) as u8);
+            assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, 0);
+        }
+
+        // On underflow the timer wraps to 0xFF, and counts down once per clock from then on
+        clock_n(&mut riot, 1);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, TIMER_FLAG);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, TIMER_FLAG);
+        assert_eq!(riot.read(PiaAddress::INTIM), 0xff);
+        clock_n(&mut riot, 1);
+        assert_eq!(riot.read(PiaAddress::INTIM), 0xfe);
+        clock_n(&mut riot, 1);
+        assert_eq!(riot.read(PiaAddress::INTIM), 0xfd);
+
+        // Reading INTIM cleared the flag
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, 0);
+    }
+
+    #[test]
+    fn timer_write_after_underflow_restores_interval() {
+        let mut riot = RIOT::new();
+        riot.write(PiaAddress::TIM8T, 1);
+        clock_n(&mut riot, 9);
+        assert_eq!(riot.timer_state().instat, TIMER_FLAG);
+        assert_eq!(riot.read(PiaAddress::INTIM), 0xff);
+
+        // Counting once per clock now, until a new interval is written
+        clock_n(&mut riot, 1);
+        assert_eq!(riot.read(PiaAddress::INTIM), 0xfe);
+
+        riot.write(PiaAddress::TIM64T, 3);
+        assert_eq!(riot.timer_state().instat, 0);
+        for t in 1..=2 * 64 {
+            riot.clock();
+            assert_eq!(riot.read(PiaAddress::INTIM), 2 - ((t - 1) / 64) as u8);
+        }
+
+        // Reading INSTAT reports an underflow without clearing it, only reading INTIM does
+        clock_n(&mut riot, 64);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, 0);
+        clock_n(&mut riot, 1);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, TIMER_FLAG);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, TIMER_FLAG);
+        riot.read(PiaAddress::INTIM);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, 0);
+    }
+
+    #[test]
+    fn timer_state_has_no_side_effects() {
+        let mut riot = RIOT::new();
+        riot.write(PiaAddress::TIM64T, 0);
+
+        let state = riot.timer_state();
+        assert_eq!(
+            state,
+            TimerState {
+                intim: 0xff,
+                instat: TIMER_FLAG,
+                resolution: 64,
+            }
+        );
+        assert!(state.underflowed());
+        assert_eq!(riot.timer_state(), state);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, TIMER_FLAG);
+    }
+
+    #[test]
+    fn timer_write_clears_timer_flag() {
+        let mut riot = RIOT::new();
+        riot.write(PiaAddress::TIM1T, 0);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, TIMER_FLAG);
+
+        riot.write(PiaAddress::TIM8T, 10);
+        assert_eq!(riot.read(PiaAddress::INSTAT) & TIMER_FLAG, 0);
+        assert_eq!(riot.read(PiaAddress::INTIM), 9);
+    }
+
+    #[test]
+    fn timer_resolution_reads_back_interval() {
+        let mut riot = RIOT::new();
+        assert_eq!(riot.timer_resolution(), 0);
+
+        riot.write(PiaAddress::TIM64T, 0x20);
+        let state = riot.timer_state();
+        assert_eq!(riot.timer_resolution(), 64);
+        assert_eq!(state.resolution, 64);
+        // The write itself counts as the first decrement
+        assert_eq!(state.intim, 0x1f);
+        assert_eq!(state.instat, 0);
+
+        riot.write(PiaAddress::T1024T, 0x01);
+        clock_n(&mut riot, 1025);
+        assert_eq!(riot.timer_state().instat, TIMER_FLAG);
+        assert_eq!(riot.timer_resolution(), 1024);
+    }
+
+    #[test]
+    fn power_cycle_keeps_switches() {
+        let mut riot = RIOT::new();
+        riot.reset(true);
+        riot.set_difficulty(0, false);
+        riot.write(PiaAddress::RAM(0x10), 0x42);
+        riot.write(PiaAddress::TIM64T, 0x20);
+
+        riot.power_cycle();
+
+        let swchb = riot.read(PiaAddress::SWCHB);
+        assert_eq!(swchb & 0x01, 0);
+        assert_eq!(swchb & 0x40, 0);
+        assert_eq!(riot.read(PiaAddress::RAM(0x10)), 0);
+        assert_eq!(riot.timer_resolution(), 0);
+    }
+
+    #[test]
+    fn reset_switch_keeps_ram() {
+        let mut riot = RIOT::new();
+        for addr in 0..128 {
+            riot.write(PiaAddress::RAM(addr), addr as u8 ^ 0x5a);
+        }
+
+        // The reset switch is just an input the game polls, unlike a power cycle
+        riot.reset(true);
+        assert_eq!(riot.read(PiaAddress::SWCHB) & 0x01, 0);
+        riot.reset(false);
+        assert_eq!(riot.read(PiaAddress::SWCHB) & 0x01, 0x01);
+
+        for addr in 0..128 {
+            assert_eq!(riot.read(PiaAddress::RAM(addr)), addr as u8 ^ 0x5a);
+        }
+    }
+
+    #[test]
+    fn random_ram_init_is_seeded() {
+        fn ram(riot: &mut RIOT) -> Vec<u8> {
+            (0..128)
+                .map(|addr| riot.read(PiaAddress::RAM(addr)))
+                .collect()
+        }
+
+        assert!(ram(&mut RIOT::new()).iter().all(|&b| b == 0));
+
+        let mut riot = RIOT::new_with_ram_init(RamInit::Random(42));
+        let random = ram(&mut riot);
+        assert!(random.iter().any(|&b| b != 0));
+        assert_eq!(
+            ram(&mut RIOT::new_with_ram_init(RamInit::Random(42))),
+            random
+        );
+        assert_ne!(
+            ram(&mut RIOT::new_with_ram_init(RamInit::Random(43))),
+            random
+        );
+
+        // Power cycling brings back the same power-on contents
+        riot.write(PiaAddress::RAM(0), !random[0]);
+        riot.power_cycle();
+        assert_eq!(ram(&mut riot), random);
+    }
+}
@@ -0,0 +1,14 @@
+//! The 6502 opcode table the CPU runs from, for tools such as assemblers, disassemblers and
+//! documentation generators.
+//!
+//! ```
+//! use atari2600_lib::tables::{AddressingMode, Instruction, OPCODES};
+//!
+//! let lda = OPCODES[0xa9];
+//! assert_eq!(lda.instruction(), Instruction::LDA);
+//! assert_eq!(lda.instruction().mnemonic(), "LDA");
+//! assert_eq!(lda.addressing_mode(), AddressingMode::Immediate);
+//! assert_eq!(lda.cycles(), 2);
+//! ```
+
+pub use crate::opcode::{AddressingMode, Instruction, Opcode, OPCODES};
@@ -0,0 +1,138 @@
+/// Register state of one of the two TIA audio channels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AudioChannel {
+    // AUDCx - ....1111 Noise/division control
+    pub audc: u8,
+    // AUDFx - ...11111 Frequency divider
+    pub audf: u8,
+    // AUDVx - ....1111 Volume
+    pub audv: u8,
+}
+
+/// Which registers of a channel differ between two `Audio` states.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelDiff {
+    pub audc: bool,
+    pub audf: bool,
+    pub audv: bool,
+}
+
+impl ChannelDiff {
+    pub fn is_empty(&self) -> bool {
+        !(self.audc || self.audf || self.audv)
+    }
+}
+
+/// The result of `Audio::diff`, one entry per channel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AudioDiff {
+    pub channels: [ChannelDiff; 2],
+}
+
+impl AudioDiff {
+    pub fn is_empty(&self) -> bool {
+        self.channels.iter().all(ChannelDiff::is_empty)
+    }
+}
+
+/// The TIA audio subsystem. Only the register state is tracked for now, no samples are generated.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Audio {
+    channels: [AudioChannel; 2],
+    // Set by any write to an AUDx register, cleared by `take_registers_changed`
+    registers_changed: bool,
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn channel(&self, channel: usize) -> &AudioChannel {
+        &self.channels[channel]
+    }
+
+    pub fn set_audc(&mut self, channel: usize, val: u8) {
+        self.channels[channel].audc = val & 0x0f;
+        self.registers_changed = true;
+    }
+
+    pub fn set_audf(&mut self, channel: usize, val: u8) {
+        self.channels[channel].audf = val & 0x1f;
+        self.registers_changed = true;
+    }
+
+    pub fn set_audv(&mut self, channel: usize, val: u8) {
+        self.channels[channel].audv = val & 0x0f;
+        self.registers_changed = true;
+    }
+
+    /// Returns whether any AUDx register has been written since the last call, and clears the
+    /// flag. Writes count even if they don't change the value.
+    pub fn take_registers_changed(&mut self) -> bool {
+        core::mem::take(&mut self.registers_changed)
+    }
+
+    pub fn snapshot(&self) -> Audio {
+        self.clone()
+    }
+
+    /// Reports which channel registers differ between `self` and `other`.
+    pub fn diff(&self, other: &Audio) -> AudioDiff {
+        let mut diff = AudioDiff::default();
+
+        for (d, (a, b)) in diff
+            .channels
+            .iter_mut()
+            .zip(self.channels.iter().zip(other.channels.iter()))
+        {
+            d.audc = a.audc != b.audc;
+            d.audf = a.audf != b.audf;
+            d.audv = a.audv != b.audv;
+        }
+
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_reports_changed_registers() {
+        let mut audio = Audio::new();
+        audio.set_audc(0, 0x04);
+        audio.set_audv(1, 0x08);
+
+        let snapshot = audio.snapshot();
+        assert!(audio.diff(&snapshot).is_empty());
+
+        audio.set_audf(0, 0x1f);
+        let diff = audio.diff(&snapshot);
+
+        assert_eq!(
+            diff.channels[0],
+            ChannelDiff {
+                audc: false,
+                audf: true,
+                audv: false,
+            }
+        );
+        assert!(diff.channels[1].is_empty());
+    }
+
+    #[test]
+    fn register_writes_set_registers_changed() {
+        let mut audio = Audio::new();
+        assert!(!audio.take_registers_changed());
+
+        audio.set_audv(1, 0x08);
+        assert!(audio.take_registers_changed());
+        assert!(!audio.take_registers_changed());
+
+        // Rewriting the same value still counts as a change
+        audio.set_audv(1, 0x08);
+        assert!(audio.take_registers_changed());
+    }
+}
@@ -1,10 +1,8 @@
 use super::graphic::Graphic;
-use super::SharedColor;
-use crate::tia::{counter::Counter, graphic::ScanCounter};
+use crate::tia::{color::Colors, counter::Counter, graphic::ScanCounter};
 
+#[derive(Clone)]
 pub(crate) struct Ball {
-    // SharedColor is an alias for Rc<RefCell<Colors>> (used for shared ownership and interior mutability)
-    colors: SharedColor,
     // Horizontal movement offset
     hmove_offset: u8,
     // Counter for managing horizontal movement and clock cycles
@@ -22,10 +20,8 @@ pub(crate) struct Ball {
 }
 
 impl Ball {
-    pub fn new(colors: SharedColor) -> Self {
+    pub fn new() -> Self {
         Self {
-            colors,
-
             hmove_offset: 0,
             ctr: Counter::default(),
 
@@ -80,11 +76,12 @@ impl Graphic for Ball {
         }
     }
 
-    fn get_color(&self) -> Option<u8> {
+    #[inline]
+    fn get_color(&self, colors: &Colors) -> Option<u8> {
         self.scan_counter
             .bit_value
             .filter(|&bit| bit)
-            .map(|_| self.colors.borrow().colupf())
+            .map(|_| colors.colupf())
     }
 
     fn should_draw_copy(&self) -> bool {
@@ -1,4 +1,14 @@
-#[derive(Default)]
+/// The last values written to the color registers, as seen by the TIA (bit 0 is unused and
+/// always reads as 0).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ColorRegisters {
+    pub colup0: u8,
+    pub colup1: u8,
+    pub colupf: u8,
+    pub colubk: u8,
+}
+
+#[derive(Default, Clone)]
 pub struct Colors {
     colup0: u8,
     colup1: u8,
@@ -42,4 +52,13 @@ impl Colors {
     pub fn colubk(&self) -> u8 {
         self.colubk
     }
+
+    pub fn registers(&self) -> ColorRegisters {
+        ColorRegisters {
+            colup0: self.colup0,
+            colup1: self.colup1,
+            colupf: self.colupf,
+            colubk: self.colubk,
+        }
+    }
 }
@@ -19,6 +19,7 @@ const RESET_VALUE: u8 = 39;
 /// Horizontal position is implicitly tracked by the counter value, and movement is
 /// implemented by making its cycle higher or lower than the current scanline.
 /// See: http://www.atarihq.com/danb/files/TIA_HW_Notes.txt
+#[derive(Clone)]
 pub(crate) struct Counter {
     period: u8,
     reset_value: u8,
@@ -72,6 +73,7 @@ impl Counter {
         self.internal_value = v;
     }
 
+    #[allow(dead_code)]
     pub fn reset_to_h1(&mut self) {
         // From TIA_HW_Notes.txt:
         //
@@ -117,8 +119,7 @@ impl Counter {
     pub fn apply_hmove(&mut self, hm_val: u8) -> HMoveResult {
         if self.movement_required {
             let clocked = self.clock();
-            self.ticks_added += 1;
-            self.movement_required = self.ticks_added != ticks_to_add(hm_val);
+            self.count_hmove_tick(hm_val);
 
             HMoveResult {
                 moved: true,
@@ -131,6 +132,22 @@ impl Counter {
             }
         }
     }
+
+    /// An extra clock that arrives while the counter is being clocked for drawing anyway, during
+    /// the visible part of the scanline, doesn't move it. It still counts towards the movement.
+    pub fn miss_hmove(&mut self, hm_val: u8) {
+        if self.movement_required {
+            self.count_hmove_tick(hm_val);
+        }
+    }
+
+    // HMxx is compared on every extra clock, so a write during the HMOVE (e.g. HMCLR) changes
+    // where the movement stops. If the counter has already gone past the new value, it keeps
+    // moving until the TIA stops handing out extra clocks.
+    fn count_hmove_tick(&mut self, hm_val: u8) {
+        self.ticks_added += 1;
+        self.movement_required = self.ticks_added != ticks_to_add(hm_val);
+    }
 }
 
 #[cfg(test)]
@@ -1,7 +1,14 @@
-use super::counter::Counter;
+use super::{color::Colors, counter::Counter};
+
+// How many clocks an object is advanced by when its RESxx register is strobed. Players end up 5
+// pixels to the right of the write during the visible part of the scanline (4 for missiles and the
+// ball), and 3 pixels from the left edge when written during horizontal blanking (2 for missiles
+// and the ball).
+const VISIBLE_RESET_CLOCKS: usize = 3;
+const HBLANK_RESET_CLOCKS: usize = 4;
 
 // Graphics Scan Counter
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ScanCounter {
     pub bit_idx: Option<isize>,
     pub bit_copies_written: usize,
@@ -19,11 +26,20 @@ pub(crate) trait Graphic {
     const GRAPHIC_SIZE: isize;
 
     // Reset method for initializing the object
-    fn reset(&mut self) {
+    fn reset(&mut self, hblank: bool) {
         self.get_counter_mut().reset();
         if self.should_draw_graphic() || self.should_draw_copy() {
             self.reset_scan_counter();
         }
+
+        let clocks = if hblank {
+            HBLANK_RESET_CLOCKS
+        } else {
+            VISIBLE_RESET_CLOCKS
+        };
+        for _ in 0..clocks {
+            self.clock();
+        }
     }
 
     // Method to start horizontal movement
@@ -57,8 +73,14 @@ pub(crate) trait Graphic {
         }
     }
 
-    // Method to get the color of the pixel
-    fn get_color(&self) -> Option<u8>;
+    // Method to count an extra HMOVE clock that doesn't move the object
+    fn miss_hmove(&mut self) {
+        let hmove_offset = self.get_hmove_offset();
+        self.get_counter_mut().miss_hmove(hmove_offset);
+    }
+
+    // Method to get the color of the pixel, if the object is drawing one
+    fn get_color(&self, colors: &Colors) -> Option<u8>;
 
     /// Updates the graphic scan circuit based on the current state of the TIA object.
     /// This method is responsible for advancing the graphic scan, determining
@@ -71,17 +93,32 @@ pub(crate) trait Graphic {
     /// - If the end of the graphic is reached, the bit index is set to `None`.
     /// - If the scan counter is inactive, the bit value is set to `None`.
     fn tick_graphic_circuit(&mut self) {
-        let pixel_bit = self.pixel_bit();
         let size = self.size();
         let graphic_size = self.graphic_size();
         let scan_counter = self.get_scan_counter_mut();
 
-        if let Some(mut idx) = scan_counter.bit_idx {
+        if let Some(idx) = scan_counter.bit_idx {
             if !(0..8).contains(&idx) {
                 scan_counter.bit_idx = Some(idx + 1);
                 return;
             }
 
+            // The size is read live, so a write to NUSIZx/CTRLPF mid-draw can shrink it below
+            // the number of copies already written. The current bit is then already complete.
+            if scan_counter.bit_copies_written >= size {
+                scan_counter.bit_copies_written = 0;
+                scan_counter.bit_idx = if idx + 1 == graphic_size {
+                    None
+                } else {
+                    Some(idx + 1)
+                };
+            }
+        }
+
+        let pixel_bit = self.pixel_bit();
+        let scan_counter = self.get_scan_counter_mut();
+
+        if let Some(mut idx) = scan_counter.bit_idx {
             scan_counter.bit_value = Some(pixel_bit);
             scan_counter.bit_copies_written += 1;
 
@@ -134,3 +171,43 @@ pub(crate) trait Graphic {
     // Method to get the hmove offset value
     fn get_hmove_offset(&self) -> u8;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tia::{player::Player, PlayerType};
+
+    #[test]
+    fn scan_counter_init_delay() {
+        const GRAPHIC: u8 = 0b1100_1010;
+
+        let mut player = Player::new(PlayerType::Player0);
+        player.set_graphic(GRAPHIC);
+        player.reset_scan_counter();
+
+        // The bit index counts up from -INIT_DELAY without drawing anything...
+        for tick in 0..Player::INIT_DELAY {
+            let scan_counter = player.get_scan_counter_mut();
+            assert_eq!(scan_counter.bit_idx, Some(tick - Player::INIT_DELAY));
+            player.tick_graphic_circuit();
+            assert_eq!(
+                player.get_scan_counter_mut().bit_value,
+                None,
+                "tick {}",
+                tick
+            );
+        }
+        assert_eq!(player.get_scan_counter_mut().bit_idx, Some(0));
+
+        // ...then the graphic is drawn one bit per tick, starting at the most significant bit
+        for bit in 0..8 {
+            player.tick_graphic_circuit();
+            let expected = (GRAPHIC >> (7 - bit)) & 1 != 0;
+            assert_eq!(player.get_scan_counter_mut().bit_value, Some(expected));
+        }
+
+        player.tick_graphic_circuit();
+        let scan_counter = player.get_scan_counter_mut();
+        assert_eq!((scan_counter.bit_idx, scan_counter.bit_value), (None, None));
+    }
+}
@@ -1,9 +1,10 @@
 use super::graphic::Graphic;
-use super::SharedColor;
-use crate::tia::{counter::Counter, graphic::ScanCounter, player::Player, PlayerType};
+use crate::tia::{
+    color::Colors, counter::Counter, graphic::ScanCounter, player::Player, PlayerType,
+};
 
+#[derive(Clone)]
 pub(crate) struct Missile {
-    colors: SharedColor,
     hmove_offset: u8,
     ctr: Counter,
     scan_counter: ScanCounter,
@@ -16,15 +17,14 @@ pub(crate) struct Missile {
 }
 
 impl Missile {
-    pub fn new(colors: SharedColor, sibling_player: PlayerType) -> Self {
+    pub fn new(sibling_player: PlayerType) -> Self {
         Self {
-            colors,
             sibling_player,
 
             enabled: false,
             hmove_offset: 0,
             nusiz: 0,
-            size: 0,
+            size: 1,
             copies: 0,
             ctr: Counter::default(),
 
@@ -46,6 +46,10 @@ impl Missile {
         self.copies = val as u8 & 0x07;
     }
 
+    pub fn nusiz(&self) -> u8 {
+        self.nusiz as u8
+    }
+
     pub fn hmclr(&mut self) {
         self.hmove_offset = 0
     }
@@ -76,12 +80,13 @@ impl Graphic for Missile {
             || (count == 15 && (copies == 0b100 || copies == 0b110))
     }
 
-    fn get_color(&self) -> Option<u8> {
+    #[inline]
+    fn get_color(&self, colors: &Colors) -> Option<u8> {
         self.scan_counter
             .bit_value
             .and_then(|bit_value| match (bit_value, &self.sibling_player) {
-                (true, PlayerType::Player0) => Some(self.colors.borrow().colup0()),
-                (true, PlayerType::Player1) => Some(self.colors.borrow().colup1()),
+                (true, PlayerType::Player0) => Some(colors.colup0()),
+                (true, PlayerType::Player1) => Some(colors.colup1()),
                 (false, _) => None,
             })
     }
@@ -1,33 +1,77 @@
+mod audio;
 mod ball;
 mod color;
 mod counter;
 mod graphic;
 mod missile;
-mod palette;
+pub(crate) mod palette;
 mod player;
 mod playfield;
 
 use crate::memory::{TiaReadAddress, TiaWriteAddress};
+#[cfg(feature = "image")]
 use image::Rgba;
-use log::debug;
-use std::{cell::RefCell, rc::Rc};
+#[cfg(feature = "image")]
+use palette::NTSC_PALETTE;
 use {
-    ball::Ball,
-    color::Colors,
-    counter::Counter,
-    graphic::Graphic,
-    missile::Missile,
-    palette::{DEFAULT_COLOR, NTSC_PALETTE},
-    player::Player,
-    playfield::Playfield,
+    ball::Ball, color::Colors, counter::Counter, graphic::Graphic, missile::Missile,
+    palette::DEFAULT_COLOR, player::Player, playfield::Playfield,
 };
 
 const LINE_LENGTH: usize = 160;
 const H_BLANK_CLOCKS: usize = 68;
+// Number of extra motion clocks an HMOVE hands out, one every 4 color clocks
+const HMOVE_PULSES: u8 = 15;
+
+pub use audio::{Audio, AudioChannel, AudioDiff, ChannelDiff};
+pub use color::ColorRegisters;
+
+/// A read-only copy of the TIA state, e.g. for a register inspector. Object positions are the
+/// values of their horizontal position counters (0-39, one step per 4 pixels).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TiaSnapshot {
+    pub colors: ColorRegisters,
+    pub nusiz0: u8,
+    pub nusiz1: u8,
+    // PF0, PF1 and PF2 in the order they are drawn on the left half of the screen
+    pub playfield: [bool; 20],
+
+    pub p0_position: u8,
+    pub p1_position: u8,
+    pub m0_position: u8,
+    pub m1_position: u8,
+    pub bl_position: u8,
+
+    pub cxm0p: u8,
+    pub cxm1p: u8,
+    pub cxp0fb: u8,
+    pub cxp1fb: u8,
+    pub cxm0fb: u8,
+    pub cxm1fb: u8,
+    pub cxblpf: u8,
+    pub cxppmm: u8,
+}
+
+/// One of the TIA's movable objects, for `EmulatorCore::object_position`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiaObjectId {
+    P0,
+    P1,
+    M0,
+    M1,
+    BL,
+}
 
-pub type SharedColor = Rc<RefCell<Colors>>;
+/// A change to VSYNC or VBLANK, made by a write to the register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncKind {
+    VsyncStart,
+    VsyncEnd,
+    VblankStart,
+    VblankEnd,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PlayerType {
     Player0,
     Player1,
@@ -85,6 +129,7 @@ impl TryFrom<u8> for VideoSignal {
 }
 
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
 pub struct TIA {
     // HSYNC counter
     ctr: Counter,
@@ -93,14 +138,26 @@ pub struct TIA {
     vsync: bool,
     vblank: u8,
     late_reset_hblank: bool,
+    // Whether HBLANK is extended to LRHB on the current scanline. Sampled from the HMOVE latch at
+    // RHB time, which is what produces the black "comb" on the left side of the screen.
+    extended_hblank: bool,
 
     // Horizontal sync
     wsync: bool,
 
+    // Extra motion clocks the HMOVE ripple counter has left to hand out
+    hmove_pulses: u8,
+
     // Input
-    // I'm only implementing player 0 joystick controls, so only one input port
-    inpt4_port: bool,
-    inpt4_latch: bool,
+    // The fire buttons of the left and right controllers, read through INPT4 and INPT5
+    fire_port: [bool; 2],
+    fire_latch: [bool; 2],
+
+    // Paddle (pot) inputs INPT0-3. Each capacitor is grounded while D7 of VBLANK is set, and
+    // once released charges for as many clocks as the paddle's resistance dictates before the
+    // input reads HIGH. A threshold of `None` means nothing is connected, so it never charges.
+    paddle_charge: [u32; 4],
+    paddle_threshold: [Option<u32>; 4],
 
     // Collision registers
     cxm0p: u8,
@@ -112,7 +169,17 @@ pub struct TIA {
     cxblpf: u8,
     cxppmm: u8,
 
-    colors: SharedColor,
+    colors: Colors,
+
+    // Bit n is set once the register at write address n has been written, see
+    // `take_registers_written`
+    registers_written: u64,
+    // The last change to VSYNC or VBLANK, see `take_sync_change`
+    sync_change: Option<SyncKind>,
+    // Whether WSYNC has been written since `take_wsync_written`
+    wsync_written: bool,
+
+    audio: Audio,
 
     // Graphics
     pf: Playfield,
@@ -122,21 +189,23 @@ pub struct TIA {
     m1: Missile,
     bl: Ball,
 
-    // One scanline of pixels to be rendered. It's up to the calling code to call
+    // One scanline of pixels to be rendered, both as color codes and converted through the
+    // palette. It's up to the calling code to call `get_scanline_indices` or
     // `get_scanline_pixels` at the end of each scanline.
+    indices: [u8; LINE_LENGTH],
+    #[cfg(feature = "image")]
     pixels: [Rgba<u8>; LINE_LENGTH],
 }
 
 impl Default for TIA {
     fn default() -> Self {
-        let colors = Rc::new(RefCell::new(Colors::new()));
         let hsync_ctr = Counter::new(57, 0);
-        let pf = Playfield::new(colors.clone());
-        let bl = Ball::new(colors.clone());
-        let m0 = Missile::new(colors.clone(), PlayerType::Player0);
-        let m1 = Missile::new(colors.clone(), PlayerType::Player1);
-        let p0 = Player::new(colors.clone(), PlayerType::Player0);
-        let p1 = Player::new(colors.clone(), PlayerType::Player1);
+        let pf = Playfield::new();
+        let bl = Ball::new();
+        let m0 = Missile::new(PlayerType::Player0);
+        let m1 = Missile::new(PlayerType::Player1);
+        let p0 = Player::new(PlayerType::Player0);
+        let p1 = Player::new(PlayerType::Player1);
 
         Self {
             ctr: hsync_ctr,
@@ -144,15 +213,21 @@ impl Default for TIA {
             vsync: false,
             vblank: 0,
             late_reset_hblank: false,
+            extended_hblank: false,
 
             wsync: false,
 
+            hmove_pulses: 0,
+
             // These two ports have latches that are both enabled by writing a "1" or disabled by
             // writing a "0" to D6 of VBLANK. When disabled, the microprocessor reads the logic
             // level of the port directly. When enabled, the latch is set for logic one and remains
             // that way until its port goes LOW.
-            inpt4_port: false,
-            inpt4_latch: true,
+            fire_port: [false; 2],
+            fire_latch: [true; 2],
+
+            paddle_charge: [0; 4],
+            paddle_threshold: [None; 4],
 
             cxm0p: 0,
             cxm1p: 0,
@@ -163,7 +238,13 @@ impl Default for TIA {
             cxblpf: 0,
             cxppmm: 0,
 
-            colors,
+            colors: Colors::new(),
+
+            registers_written: 0,
+            sync_change: None,
+            wsync_written: false,
+
+            audio: Audio::new(),
 
             pf,
             bl,
@@ -172,6 +253,8 @@ impl Default for TIA {
             p0,
             p1,
 
+            indices: [0; LINE_LENGTH],
+            #[cfg(feature = "image")]
             pixels: [Rgba([0, 0, 0, 0]); LINE_LENGTH],
         }
     }
@@ -194,28 +277,186 @@ impl TIA {
         self.wsync
     }
 
+    pub fn colors(&self) -> ColorRegisters {
+        self.colors.registers()
+    }
+
+    pub fn snapshot(&self) -> TiaSnapshot {
+        TiaSnapshot {
+            colors: self.colors(),
+            nusiz0: self.m0.nusiz(),
+            nusiz1: self.m1.nusiz(),
+            playfield: self.pf.bits(),
+
+            p0_position: self.p0.get_counter().value(),
+            p1_position: self.p1.get_counter().value(),
+            m0_position: self.m0.get_counter().value(),
+            m1_position: self.m1.get_counter().value(),
+            bl_position: self.bl.get_counter().value(),
+
+            cxm0p: self.cxm0p,
+            cxm1p: self.cxm1p,
+            cxp0fb: self.cxp0fb,
+            cxp1fb: self.cxp1fb,
+            cxm0fb: self.cxm0fb,
+            cxm1fb: self.cxm1fb,
+            cxblpf: self.cxblpf,
+            cxppmm: self.cxppmm,
+        }
+    }
+
+    // The object's horizontal position counter, 0-159. It counts one step per visible pixel, and
+    // the object starts drawing a few pixels after it wraps around to 0.
+    pub fn object_position(&self, object: TiaObjectId) -> u8 {
+        let counter = match object {
+            TiaObjectId::P0 => self.p0.get_counter(),
+            TiaObjectId::P1 => self.p1.get_counter(),
+            TiaObjectId::M0 => self.m0.get_counter(),
+            TiaObjectId::M1 => self.m1.get_counter(),
+            TiaObjectId::BL => self.bl.get_counter(),
+        };
+        counter.internal_value
+    }
+
+    // Returns the last change to VSYNC or VBLANK since the last call. The CPU can only write once
+    // per cycle, so checking after each CPU cycle catches all of them.
+    pub fn take_sync_change(&mut self) -> Option<SyncKind> {
+        self.sync_change.take()
+    }
+
+    // Returns whether WSYNC has been written since the last call
+    pub fn take_wsync_written(&mut self) -> bool {
+        core::mem::take(&mut self.wsync_written)
+    }
+
+    // Returns which registers have been written since the last call, one bit per write address
+    pub fn take_registers_written(&mut self) -> u64 {
+        core::mem::take(&mut self.registers_written)
+    }
+
+    pub fn audio(&self) -> &Audio {
+        &self.audio
+    }
+
+    pub fn audio_mut(&mut self) -> &mut Audio {
+        &mut self.audio
+    }
+
+    pub fn get_scanline_indices(&self) -> &[u8; LINE_LENGTH] {
+        &self.indices
+    }
+
+    #[cfg(feature = "image")]
     pub fn get_scanline_pixels(&self) -> &[Rgba<u8>; LINE_LENGTH] {
         &self.pixels
     }
 
-    pub fn joystick_fire(&mut self, pressed: bool) {
-        self.inpt4_port = !pressed;
+    // Returns the chip to its power-on state, keeping the state of the controllers plugged into it
+    pub fn power_cycle(&mut self) {
+        *self = Self {
+            fire_port: self.fire_port,
+            paddle_threshold: self.paddle_threshold,
+            ..Self::default()
+        };
+    }
+
+    // Presses or releases the fire button of the left (0) or right (1) controller
+    pub fn set_fire(&mut self, port: usize, pressed: bool) {
+        self.fire_port[port] = !pressed;
+        self.update_latches();
+    }
+
+    // Connects the paddle read through INPT0-3 and sets its position, or disconnects it. Each step
+    // of the position adds one scanline to the time the capacitor takes to charge.
+    pub fn set_paddle(&mut self, port: usize, position: Option<u8>) {
+        assert!(port < 4, "paddle port {} out of range", port);
+        self.paddle_threshold[port] =
+            position.map(|p| p as u32 * (H_BLANK_CLOCKS + LINE_LENGTH) as u32);
+    }
 
-        if !self.inpt4_port {
-            // When the port goes LOW the latch goes LOW and remains that way (until re-disabled by
-            // VBLANK Bit 6) regardless of what the port does
-            self.inpt4_latch = false;
+    fn latches_enabled(&self) -> bool {
+        (self.vblank & 0x40) != 0
+    }
+
+    fn update_latches(&mut self) {
+        if !self.latches_enabled() {
+            return;
+        }
+        for (port, latch) in self.fire_port.iter().zip(self.fire_latch.iter_mut()) {
+            if !port {
+                // When the port goes LOW the latch goes LOW and remains that way (until re-disabled
+                // by VBLANK Bit 6) regardless of what the port does
+                *latch = false;
+            }
         }
     }
 
     fn reset_latches(&mut self) {
-        self.inpt4_latch = true
+        self.fire_latch = [true; 2]
+    }
+
+    fn read_fire(&self, port: usize) -> u8 {
+        // Check the logic level of the port
+        let mut level = self.fire_port[port];
+
+        // When the latch is enabled in D6 of VBLANK, check the latch value aswell
+        if self.latches_enabled() {
+            level = level && self.fire_latch[port];
+        }
+
+        if level {
+            0x80
+        } else {
+            0x00
+        }
+    }
+
+    pub fn paddles_dumped(&self) -> bool {
+        (self.vblank & 0x80) != 0
+    }
+
+    fn charge_paddles(&mut self) {
+        if !self.paddles_dumped() {
+            for charge in self.paddle_charge.iter_mut() {
+                *charge = charge.saturating_add(1);
+            }
+        }
+    }
+
+    fn read_paddle(&self, port: usize) -> u8 {
+        match self.paddle_threshold[port] {
+            Some(threshold) if !self.paddles_dumped() && self.paddle_charge[port] >= threshold => {
+                0x80
+            }
+            _ => 0x00,
+        }
     }
 
     // Resolve playfield/player/missile/ball priorities and return the color to
     // be rendered.
+    #[inline]
     fn get_pixel_color(&self) -> u8 {
-        if !self.pf.priority() {
+        let colors = &self.colors;
+
+        if self.pf.score_mode() {
+            // In SCORE-mode, each half of the playfield takes the color and the priority of
+            // the player on that side, leaving only the ball at priority 3.
+            let (pf_left, pf_right) = if self.pf.left_side() {
+                (self.pf.get_color(colors), None)
+            } else {
+                (None, self.pf.get_color(colors))
+            };
+
+            self.p0
+                .get_color(colors)
+                .or(self.m0.get_color(colors))
+                .or(pf_left)
+                .or(self.p1.get_color(colors))
+                .or(self.m1.get_color(colors))
+                .or(pf_right)
+                .or(self.bl.get_color(colors))
+                .unwrap_or(colors.colubk())
+        } else if !self.pf.priority() {
             // When pixels of two or more objects overlap each other, only the
             // pixel of the object with topmost priority is drawn to the screen.
             // The normal priority ordering is:
@@ -227,13 +468,13 @@ impl TIA {
             //  4 (lowest)   COLUBK   BK
 
             self.p0
-                .get_color()
-                .or(self.m0.get_color())
-                .or(self.p1.get_color())
-                .or(self.m1.get_color())
-                .or(self.bl.get_color())
-                .or(self.pf.get_color())
-                .unwrap_or(self.colors.borrow().colubk())
+                .get_color(colors)
+                .or(self.m0.get_color(colors))
+                .or(self.p1.get_color(colors))
+                .or(self.m1.get_color(colors))
+                .or(self.bl.get_color(colors))
+                .or(self.pf.get_color(colors))
+                .unwrap_or(colors.colubk())
         } else {
             // Optionally, the playfield and ball may be assigned to have higher
             // priority (by setting CTRLPF.2). The priority ordering is then:
@@ -245,26 +486,27 @@ impl TIA {
             //  4 (lowest)   COLUBK   BK
 
             self.pf
-                .get_color()
-                .or(self.bl.get_color())
-                .or(self.p0.get_color())
-                .or(self.m0.get_color())
-                .or(self.p1.get_color())
-                .or(self.m1.get_color())
-                .unwrap_or(self.colors.borrow().colubk())
+                .get_color(colors)
+                .or(self.bl.get_color(colors))
+                .or(self.p0.get_color(colors))
+                .or(self.m0.get_color(colors))
+                .or(self.p1.get_color(colors))
+                .or(self.m1.get_color(colors))
+                .unwrap_or(colors.colubk())
         }
     }
 
     fn update_collisions(&mut self) {
         const BIT_6: u8 = 0x40;
         const BIT_7: u8 = 0x80;
+        let colors = &self.colors;
 
         macro_rules! check_collision {
             ($register: ident, $a: expr, $b: expr, $c: expr) => {
-                if $a.get_color().is_some() && $b.get_color().is_some() {
+                if $a.get_color(colors).is_some() && $b.get_color(colors).is_some() {
                     self.$register |= BIT_6;
                 }
-                if $a.get_color().is_some() && $c.get_color().is_some() {
+                if $a.get_color(colors).is_some() && $c.get_color(colors).is_some() {
                     self.$register |= BIT_7;
                 }
             };
@@ -278,15 +520,15 @@ impl TIA {
         check_collision!(cxm1fb, self.m1, self.bl, self.pf);
 
         // bit 6 of CXLBPF is unused
-        if self.bl.get_color().is_some() && self.pf.get_color().is_some() {
+        if self.bl.get_color(colors).is_some() && self.pf.get_color(colors).is_some() {
             self.cxblpf |= BIT_7
         }
 
-        if self.m0.get_color().is_some() && self.m1.get_color().is_some() {
+        if self.m0.get_color(colors).is_some() && self.m1.get_color(colors).is_some() {
             self.cxppmm |= BIT_6
         }
 
-        if self.p0.get_color().is_some() && self.p1.get_color().is_some() {
+        if self.p0.get_color(colors).is_some() && self.p1.get_color(colors).is_some() {
             self.cxppmm |= BIT_7
         }
     }
@@ -295,24 +537,26 @@ impl TIA {
         self.ctr.value() > Signals::RHB && self.ctr.value() <= Signals::END
     }
 
+    fn in_hblank(&self) -> bool {
+        !self.visible_cycle() || self.in_late_reset()
+    }
+
     fn in_late_reset(&self) -> bool {
-        self.late_reset_hblank
-            && self.ctr.value() > Signals::RHB
-            && self.ctr.value() <= Signals::LRHB
+        self.extended_hblank && self.ctr.value() > Signals::RHB && self.ctr.value() <= Signals::LRHB
     }
 
     pub fn clock(&mut self) {
         // Clock the horizontal sync counter
         let clocked = self.ctr.clock();
 
+        self.charge_paddles();
+
         if self.visible_cycle() {
             self.set_pixel();
-        } else {
-            // During HBLANK we apply extra HMOVE clocks
-            self.apply_hmove_all();
         }
 
         if clocked {
+            self.clock_hmove();
             if let Ok(signal) = self.ctr.value().try_into() {
                 self.handle_video_signal(signal);
             }
@@ -323,21 +567,26 @@ impl TIA {
         // Playfield is clocked on every visible cycle
         self.pf.clock();
 
-        // Update the collision registers
-        self.update_collisions();
-
         let color = if self.in_late_reset() {
-            // During LRHB we apply extra HMOVE clocks
-            self.apply_hmove_all();
+            // Nothing is drawn until LRHB, so there are no collisions either
             DEFAULT_COLOR
         } else {
+            // Update the collision registers
+            self.update_collisions();
+
             // Player, missile, and ball counters only get clocked on visible cycles
             self.clock_visible_components();
             self.get_pixel_color() as usize
         };
 
         let x = self.ctr.internal_value as usize - H_BLANK_CLOCKS;
-        self.pixels[x] = NTSC_PALETTE[color];
+        // Bit 0 of the color registers is unused, which leaves 128 colors
+        let index = color >> 1;
+        self.indices[x] = index as u8;
+        #[cfg(feature = "image")]
+        {
+            self.pixels[x] = NTSC_PALETTE[index];
+        }
     }
 
     fn handle_video_signal(&mut self, signal: VideoSignal) {
@@ -350,6 +599,8 @@ impl TIA {
                 // electron beam reaches the right edge of the screen.
                 self.wsync = false;
                 self.late_reset_hblank = false;
+                self.extended_hblank = false;
+                self.pf.start_scanline();
             }
             VideoSignal::SHS => {
                 // The SHS signal is used to set the horizontal sync HS signal and, together with RHS, it shapes it.
@@ -362,6 +613,8 @@ impl TIA {
             }
             VideoSignal::RHB => {
                 // The RHB signal resets the HBLANK HB signal. It can be ignored for LRHB depending on the HMOVEL latch.
+                // An HMOVE strobed after this point is too late to extend the current HBLANK.
+                self.extended_hblank = self.late_reset_hblank;
             }
             VideoSignal::LRHB => {
                 // The LRHB signal resets the HBLANK HB signal later. It can be ignored for RHB depending on the HMOVEL latch.
@@ -375,6 +628,26 @@ impl TIA {
         }
     }
 
+    // The HMOVE ripple counter hands out an extra motion clock every 4 color clocks, to every
+    // object that hasn't moved as far as its HMxx register asks yet. Objects are only clocked for
+    // drawing outside HBLANK, so that is the only time the extra clocks move them.
+    fn clock_hmove(&mut self) {
+        if self.hmove_pulses == 0 {
+            return;
+        }
+        self.hmove_pulses -= 1;
+
+        if self.in_hblank() {
+            self.apply_hmove_all();
+        } else {
+            self.p0.miss_hmove();
+            self.p1.miss_hmove();
+            self.m0.miss_hmove();
+            self.m1.miss_hmove();
+            self.bl.miss_hmove();
+        }
+    }
+
     // Helper method to apply extra HMOVE clocks to all components
     fn apply_hmove_all(&mut self) {
         self.p0.apply_hmove();
@@ -395,7 +668,8 @@ impl TIA {
 }
 
 impl TIA {
-    pub fn read(&mut self, address: TiaReadAddress) -> u8 {
+    // Reading the TIA has no side effects, the collision latches are only cleared by CXCLR
+    pub fn read(&self, address: TiaReadAddress) -> u8 {
         use TiaReadAddress::*;
         match address {
             CXM0P => self.cxm0p,
@@ -406,41 +680,62 @@ impl TIA {
             CXM1FB => self.cxm1fb,
             CXBLPF => self.cxblpf,
             CXPPMM => self.cxppmm,
-            INPT4 => {
-                // Check the logic level of the port
-                let mut level = self.inpt4_port;
-
-                // When the latch is enabled in D6 of VBLANK, check the latch value aswell
-                if (self.vblank & 0x40) != 0 {
-                    level = level && self.inpt4_latch;
-                }
-
-                if level {
-                    0x80
-                } else {
-                    0x00
-                }
-            }
-            _ => 0,
+            INPT0 => self.read_paddle(0),
+            INPT1 => self.read_paddle(1),
+            INPT2 => self.read_paddle(2),
+            INPT3 => self.read_paddle(3),
+            INPT4 => self.read_fire(0),
+            INPT5 => self.read_fire(1),
         }
     }
 
     pub fn write(&mut self, address: TiaWriteAddress, val: u8) {
         use TiaWriteAddress::*;
+        self.registers_written |= 1 << address as u8;
+
         match address {
             //
             // Frame timing and synchronisation
             //
-            VSYNC => self.vsync = (val & 0x02) != 0,
+            VSYNC => {
+                let vsync = (val & 0x02) != 0;
+                if vsync != self.vsync {
+                    self.sync_change = Some(if vsync {
+                        SyncKind::VsyncStart
+                    } else {
+                        SyncKind::VsyncEnd
+                    });
+                }
+                self.vsync = vsync;
+            }
             VBLANK => {
+                let vblank = (val & 0x02) != 0;
+                if vblank != self.in_vblank() {
+                    self.sync_change = Some(if vblank {
+                        SyncKind::VblankStart
+                    } else {
+                        SyncKind::VblankEnd
+                    });
+                }
                 self.vblank = val;
 
-                if (val & 0x80) != 0 {
-                    // INPT4-5 latches are reset when D6 of VBLANK is 1
+                if self.latches_enabled() {
+                    // An enabled latch starts out at logic one, unless the port is already LOW
+                    self.update_latches();
+                } else {
+                    // INPT4-5 latches are held at logic one while disabled by D6 of VBLANK
                     self.reset_latches();
                 }
+
+                if self.paddles_dumped() {
+                    // INPT0-3 capacitors are grounded when D7 of VBLANK is 1
+                    self.paddle_charge = [0; 4];
+                }
+            }
+            WSYNC => {
+                self.wsync = true;
+                self.wsync_written = true;
             }
-            WSYNC => self.wsync = true,
             // TODO: Commenting this out fixes the frame shifted bown by 1 pixel
             // RSYNC   <strobe>  reset horizontal sync counter
             // from TIA_HW_Notes.txt:
@@ -453,10 +748,10 @@ impl TIA {
             //
             // Colors
             //
-            COLUP0 => self.colors.borrow_mut().set_colup0(val & 0xfe),
-            COLUP1 => self.colors.borrow_mut().set_colup1(val & 0xfe),
-            COLUPF => self.colors.borrow_mut().set_colupf(val & 0xfe),
-            COLUBK => self.colors.borrow_mut().set_colubk(val & 0xfe),
+            COLUP0 => self.colors.set_colup0(val & 0xfe),
+            COLUP1 => self.colors.set_colup1(val & 0xfe),
+            COLUPF => self.colors.set_colupf(val & 0xfe),
+            COLUBK => self.colors.set_colubk(val & 0xfe),
             CTRLPF => {
                 self.pf.set_control(val);
                 self.bl.set_nusiz(1 << ((val & 0b0011_0000) >> 4));
@@ -491,32 +786,25 @@ impl TIA {
                 // then the position is set to the left edge of the screen (plus
                 // a few pixels towards right: 3 pixels for P0/P1, and only 2
                 // pixels for M0/M1/BL).
-                self.p0.reset();
+                self.p0.reset(self.in_hblank());
             }
             RESP1 => {
-                self.p1.reset();
-            }
-            RESM0 => self.m0.reset(),
-            RESM1 => self.m1.reset(),
-            RESBL => self.bl.reset(),
-            AUDC0 => {
-                debug!("AUDC0: {}", val)
-            }
-            AUDC1 => {
-                debug!("AUDC1: {}", val)
-            }
-            AUDF0 => {
-                debug!("AUDF0: {}", val)
-            }
-            AUDF1 => {
-                debug!("AUDF1: {}", val)
-            }
-            AUDV0 => {
-                debug!("AUDV0: {}", val)
-            }
-            AUDV1 => {
-                debug!("AUDV1: {}", val)
+                self.p1.reset(self.in_hblank());
             }
+            RESM0 => self.m0.reset(self.in_hblank()),
+            RESM1 => self.m1.reset(self.in_hblank()),
+            RESBL => self.bl.reset(self.in_hblank()),
+
+            //
+            // Audio
+            //
+            AUDC0 => self.audio.set_audc(0, val),
+            AUDC1 => self.audio.set_audc(1, val),
+            AUDF0 => self.audio.set_audf(0, val),
+            AUDF1 => self.audio.set_audf(1, val),
+            AUDV0 => self.audio.set_audv(0, val),
+            AUDV1 => self.audio.set_audv(1, val),
+
             GRP0 => {
                 self.p0.set_graphic(val);
                 self.p1.set_vdel_value();
@@ -558,6 +846,9 @@ impl TIA {
                 self.p0.start_hmove();
                 self.p1.start_hmove();
 
+                // Strobing HMOVE again restarts the ripple counter, handing out another set of
+                // extra clocks on top of those already given
+                self.hmove_pulses = HMOVE_PULSES;
                 self.late_reset_hblank = true;
             }
             HMCLR => {
@@ -580,3 +871,842 @@ impl TIA {
         }
     }
 }
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+
+    const CLOCKS_PER_SCANLINE: usize = 228;
+
+    fn clock_n(tia: &mut TIA, n: usize) {
+        for _ in 0..n {
+            tia.clock();
+        }
+    }
+
+    // Clocks the TIA up to (but not including) the given pixel of the current scanline, assuming
+    // it is currently at the start of a scanline.
+    fn clock_to_pixel(tia: &mut TIA, x: usize) {
+        clock_n(tia, H_BLANK_CLOCKS + x);
+    }
+
+    fn color_rgba(color: u8) -> Rgba<u8> {
+        NTSC_PALETTE[color as usize >> 1]
+    }
+
+    fn first_pixel_of(tia: &TIA, color: u8) -> Option<usize> {
+        let rgba = color_rgba(color);
+        tia.get_scanline_pixels().iter().position(|&p| p == rgba)
+    }
+
+    #[test]
+    fn fire_held_when_latch_enabled() {
+        let mut tia = TIA::new();
+        tia.set_fire(0, false);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x80);
+
+        // Pressing fire and then enabling the latch leaves it LOW...
+        tia.set_fire(0, true);
+        tia.write(TiaWriteAddress::VBLANK, 0x40);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x00);
+
+        // ...even after the button is released
+        tia.set_fire(0, false);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x00);
+
+        // Writing VBLANK again with D6 still set keeps the latched value
+        tia.write(TiaWriteAddress::VBLANK, 0x42);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x00);
+    }
+
+    #[test]
+    fn latch_disable_tracks_port_and_reenable_relatches() {
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::VBLANK, 0x40);
+
+        tia.set_fire(0, true);
+        tia.set_fire(0, false);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x00);
+
+        // With the latch disabled, INPT4 follows the port directly
+        tia.write(TiaWriteAddress::VBLANK, 0x00);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x80);
+        tia.set_fire(0, true);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x00);
+        tia.set_fire(0, false);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x80);
+
+        // Re-enabling the latch starts it at logic one, and it latches the next press
+        tia.write(TiaWriteAddress::VBLANK, 0x40);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x80);
+        tia.set_fire(0, true);
+        tia.set_fire(0, false);
+        assert_eq!(tia.read(TiaReadAddress::INPT4), 0x00);
+    }
+
+    #[test]
+    fn paddles_dumped_to_ground() {
+        let mut tia = TIA::new();
+        tia.paddle_threshold[0] = Some(100);
+        clock_n(&mut tia, 100);
+        assert_eq!(tia.read(TiaReadAddress::INPT0), 0x80);
+
+        // Grounding the capacitor reads 0 immediately, and holds it there
+        tia.write(TiaWriteAddress::VBLANK, 0x80);
+        assert_eq!(tia.read(TiaReadAddress::INPT0), 0x00);
+        clock_n(&mut tia, 1000);
+        assert_eq!(tia.read(TiaReadAddress::INPT0), 0x00);
+
+        // Once released, it takes a while for the capacitor to charge again
+        tia.write(TiaWriteAddress::VBLANK, 0x00);
+        clock_n(&mut tia, 99);
+        assert_eq!(tia.read(TiaReadAddress::INPT0), 0x00);
+        clock_n(&mut tia, 1);
+        assert_eq!(tia.read(TiaReadAddress::INPT0), 0x80);
+
+        // Nothing is connected to the other ports
+        assert_eq!(tia.read(TiaReadAddress::INPT1), 0x00);
+    }
+
+    #[test]
+    fn each_paddle_charges_independently() {
+        const INPUTS: [TiaReadAddress; 4] = [
+            TiaReadAddress::INPT0,
+            TiaReadAddress::INPT1,
+            TiaReadAddress::INPT2,
+            TiaReadAddress::INPT3,
+        ];
+        const LINE: usize = H_BLANK_CLOCKS + LINE_LENGTH;
+
+        let mut tia = TIA::new();
+        for (port, position) in [4, 1, 3, 2].into_iter().enumerate() {
+            tia.set_paddle(port, Some(position));
+        }
+
+        let charged = |tia: &TIA| INPUTS.map(|r| tia.read(r) != 0);
+
+        // Ports 1, 3, 2 and 0 cross their thresholds one scanline apart, in that order
+        let mut expected = [false; 4];
+        for port in [1, 3, 2, 0] {
+            clock_n(&mut tia, LINE - 1);
+            assert_eq!(charged(&tia), expected);
+            clock_n(&mut tia, 1);
+            expected[port] = true;
+            assert_eq!(charged(&tia), expected);
+        }
+
+        // Grounding discharges all four
+        tia.write(TiaWriteAddress::VBLANK, 0x80);
+        tia.write(TiaWriteAddress::VBLANK, 0x00);
+        for input in INPUTS {
+            assert_eq!(tia.read(input), 0x00);
+        }
+        clock_n(&mut tia, LINE);
+        assert_eq!(tia.read(TiaReadAddress::INPT1), 0x80);
+        assert_eq!(tia.read(TiaReadAddress::INPT3), 0x00);
+    }
+
+    #[test]
+    fn reset_position_offsets() {
+        const COLOR: u8 = 0x44;
+
+        // Resets a single object on the second scanline, either during HBLANK or at the given
+        // pixel, and returns its counter value and the column it is drawn at on the next scanline.
+        fn reset_at(object: TiaWriteAddress, at: Option<usize>) -> (u8, Option<usize>) {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUP0, COLOR);
+            tia.write(TiaWriteAddress::COLUPF, COLOR);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+
+            let x = match at {
+                Some(x) => H_BLANK_CLOCKS + x,
+                None => H_BLANK_CLOCKS / 2,
+            };
+            clock_n(&mut tia, x);
+
+            let counter = match object {
+                TiaWriteAddress::RESP0 => {
+                    tia.write(object, 0);
+                    tia.write(TiaWriteAddress::GRP0, 0x80);
+                    tia.p0.get_counter().value()
+                }
+                TiaWriteAddress::RESM0 => {
+                    tia.write(object, 0);
+                    tia.write(TiaWriteAddress::ENAM0, 0x02);
+                    tia.m0.get_counter().value()
+                }
+                _ => {
+                    tia.write(object, 0);
+                    tia.write(TiaWriteAddress::ENABL, 0x02);
+                    tia.bl.get_counter().value()
+                }
+            };
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - x);
+
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            (counter, first_pixel_of(&tia, COLOR))
+        }
+
+        assert_eq!(reset_at(TiaWriteAddress::RESP0, None), (0, Some(3)));
+        assert_eq!(reset_at(TiaWriteAddress::RESM0, None), (0, Some(2)));
+        assert_eq!(reset_at(TiaWriteAddress::RESBL, None), (0, Some(2)));
+
+        assert_eq!(reset_at(TiaWriteAddress::RESP0, Some(40)), (39, Some(45)));
+        assert_eq!(reset_at(TiaWriteAddress::RESM0, Some(40)), (39, Some(44)));
+        assert_eq!(reset_at(TiaWriteAddress::RESBL, Some(40)), (39, Some(44)));
+    }
+
+    fn count_pixels_of(tia: &TIA, color: u8) -> usize {
+        let rgba = color_rgba(color);
+        tia.get_scanline_pixels()
+            .iter()
+            .filter(|&&p| p == rgba)
+            .count()
+    }
+
+    #[test]
+    fn object_position_tracks_drawn_column() {
+        const COLOR: u8 = 0x44;
+
+        for (object, reset, enable, delay) in [
+            (
+                TiaObjectId::P0,
+                TiaWriteAddress::RESP0,
+                TiaWriteAddress::GRP0,
+                5,
+            ),
+            (
+                TiaObjectId::M1,
+                TiaWriteAddress::RESM1,
+                TiaWriteAddress::ENAM1,
+                4,
+            ),
+            (
+                TiaObjectId::BL,
+                TiaWriteAddress::RESBL,
+                TiaWriteAddress::ENABL,
+                4,
+            ),
+        ] {
+            for x in [0, 40, 80, 155] {
+                let mut tia = TIA::new();
+                tia.write(TiaWriteAddress::COLUP0, COLOR);
+                tia.write(TiaWriteAddress::COLUP1, COLOR);
+                tia.write(TiaWriteAddress::COLUPF, COLOR);
+                tia.write(enable, 0xff);
+                clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+                clock_to_pixel(&mut tia, x);
+                tia.write(reset, 0);
+                let at_reset = tia.object_position(object);
+                clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - x);
+
+                // Counters aren't clocked during HBLANK, so this is where the next scanline starts
+                let position = tia.object_position(object) as usize;
+                clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+                let column = first_pixel_of(&tia, COLOR).unwrap();
+
+                assert_eq!(at_reset, 159, "{:?} reset at {}", object, x);
+                assert_eq!(
+                    (158 + delay - position) % 160,
+                    column,
+                    "{:?} reset at {}",
+                    object,
+                    x
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn missile_width_from_nusiz() {
+        const COLUP0: u8 = 0x44;
+
+        for (nusiz, lit) in [
+            (0x00, 1),
+            (0x10, 2),
+            (0x20, 4),
+            (0x30, 8),
+            (0x33, 24),
+            (0x35, 8),
+        ] {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUP0, COLUP0);
+            tia.write(TiaWriteAddress::NUSIZ0, nusiz);
+            tia.write(TiaWriteAddress::ENAM0, 0x02);
+
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            clock_to_pixel(&mut tia, 20);
+            tia.write(TiaWriteAddress::RESM0, 0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 20);
+
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            assert_eq!(count_pixels_of(&tia, COLUP0), lit, "NUSIZ0 = {:02X}", nusiz);
+        }
+    }
+
+    #[test]
+    fn ball_size_change_mid_draw() {
+        const COLUPF: u8 = 0x44;
+
+        // Draws an 8 pixel wide ball at pixel 40, switching CTRLPF to the given ball size after
+        // `after` pixels of it have been drawn. Returns the number of ball pixels on the scanline.
+        fn draw_ball(after: usize, ctrlpf: u8) -> usize {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUPF, COLUPF);
+            tia.write(TiaWriteAddress::CTRLPF, 0x30);
+            tia.write(TiaWriteAddress::ENABL, 0x02);
+
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            clock_to_pixel(&mut tia, 36);
+            tia.write(TiaWriteAddress::RESBL, 0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 36);
+
+            // The ball starts at pixel 40, so pixels 40..40 + after have been drawn after this
+            clock_to_pixel(&mut tia, 39 + after);
+            tia.write(TiaWriteAddress::CTRLPF, ctrlpf);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 39 - after);
+
+            assert_eq!(first_pixel_of(&tia, COLUPF), Some(40));
+            count_pixels_of(&tia, COLUPF)
+        }
+
+        // Widening or narrowing takes effect on the remaining pixels straight away
+        assert_eq!(draw_ball(1, 0x20), 4);
+        assert_eq!(draw_ball(3, 0x20), 4);
+        // Narrowing below what has already been drawn ends the ball on the next pixel
+        assert_eq!(draw_ball(5, 0x10), 5);
+        assert_eq!(draw_ball(5, 0x00), 5);
+    }
+
+    #[test]
+    fn score_mode_ball_keeps_colupf() {
+        const COLUP0: u8 = 0x44;
+        const COLUP1: u8 = 0x86;
+        const COLUPF: u8 = 0xc8;
+        const COLUBK: u8 = 0x02;
+
+        // Draws PF0 on both halves of the screen in score mode, with an 8 pixel ball at `x`
+        fn draw(x: usize) -> [Rgba<u8>; LINE_LENGTH] {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUP0, COLUP0);
+            tia.write(TiaWriteAddress::COLUP1, COLUP1);
+            tia.write(TiaWriteAddress::COLUPF, COLUPF);
+            tia.write(TiaWriteAddress::COLUBK, COLUBK);
+            tia.write(TiaWriteAddress::CTRLPF, 0x32);
+            tia.write(TiaWriteAddress::PF0, 0xf0);
+            tia.write(TiaWriteAddress::ENABL, 0x02);
+
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            clock_to_pixel(&mut tia, x - 4);
+            tia.write(TiaWriteAddress::RESBL, 0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - (x - 4));
+
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            *tia.get_scanline_pixels()
+        }
+
+        let [p0, p1, pf, bk] = [COLUP0, COLUP1, COLUPF, COLUBK].map(color_rgba);
+
+        // The playfield halves use COLUP0/COLUP1, the ball COLUPF
+        let pixels = draw(40);
+        assert!(pixels[0..16].iter().all(|&p| p == p0));
+        assert!(pixels[16..40].iter().all(|&p| p == bk));
+        assert!(pixels[40..48].iter().all(|&p| p == pf));
+        assert!(pixels[80..96].iter().all(|&p| p == p1));
+
+        // Both playfield halves have a higher priority than the ball
+        let pixels = draw(12);
+        assert!(pixels[0..16].iter().all(|&p| p == p0));
+        assert!(pixels[16..20].iter().all(|&p| p == pf));
+
+        let pixels = draw(92);
+        assert!(pixels[80..96].iter().all(|&p| p == p1));
+        assert!(pixels[96..100].iter().all(|&p| p == pf));
+    }
+
+    #[test]
+    fn hmove_comb() {
+        const COLUBK: u8 = 0x0e;
+        const COLUP0: u8 = 0x44;
+
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUBK, COLUBK);
+        tia.write(TiaWriteAddress::COLUP0, COLUP0);
+        tia.write(TiaWriteAddress::GRP0, 0xff);
+
+        // Position player 0 in the middle of the screen
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        clock_to_pixel(&mut tia, 80);
+        tia.write(TiaWriteAddress::RESP0, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 80);
+
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        let x = first_pixel_of(&tia, COLUP0).unwrap();
+        assert_eq!(first_pixel_of(&tia, COLUBK), Some(0));
+
+        // Move player 0 seven pixels to the left
+        tia.write(TiaWriteAddress::HMP0, 0x70);
+        tia.write(TiaWriteAddress::HMOVE, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+
+        let black = color_rgba(DEFAULT_COLOR as u8);
+        assert!(tia.get_scanline_pixels()[..8].iter().all(|&p| p == black));
+        assert_eq!(first_pixel_of(&tia, COLUBK), Some(8));
+        assert_eq!(first_pixel_of(&tia, COLUP0), Some(x - 7));
+
+        // The comb only appears on the line HMOVE was strobed on
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        assert_eq!(first_pixel_of(&tia, COLUBK), Some(0));
+        assert_eq!(first_pixel_of(&tia, COLUP0), Some(x - 7));
+    }
+
+    #[test]
+    fn hmclr_after_hmove_keeps_motion_applied() {
+        const COLUP0: u8 = 0x44;
+
+        // Positions player 0, then strobes HMOVE at the start of a scanline to move it seven pixels
+        // left, and HMCLR the given number of clocks later. Returns where the player started, and
+        // where it is drawn on the line of the HMOVE and on the next one.
+        fn hmclr_after(clocks: Option<usize>) -> (usize, usize, usize) {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUP0, COLUP0);
+            tia.write(TiaWriteAddress::GRP0, 0xff);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            clock_to_pixel(&mut tia, 80);
+            tia.write(TiaWriteAddress::RESP0, 0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 80);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            let x = first_pixel_of(&tia, COLUP0).unwrap();
+
+            tia.write(TiaWriteAddress::HMP0, 0x70);
+            tia.write(TiaWriteAddress::HMOVE, 0);
+            let elapsed = match clocks {
+                Some(clocks) => {
+                    clock_n(&mut tia, clocks);
+                    tia.write(TiaWriteAddress::HMCLR, 0);
+                    clocks
+                }
+                None => 0,
+            };
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - elapsed);
+            let moved = first_pixel_of(&tia, COLUP0).unwrap();
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            (x, moved, first_pixel_of(&tia, COLUP0).unwrap())
+        }
+
+        // The extra clocks come every 4 color clocks. Cleared before the player has had the 8 of
+        // them that HMCLR asks for, it stops there and doesn't move at all.
+        for clocks in [0, 9, 30] {
+            let (x, moved, next) = hmclr_after(Some(clocks));
+            assert_eq!((moved, next), (x, x), "HMCLR {} clocks after HMOVE", clocks);
+        }
+
+        // Cleared once it has had more than that, it gets the rest of them anyway, and stops when
+        // the HMOVE ends
+        for clocks in [40, 60, 100] {
+            let (x, moved, next) = hmclr_after(Some(clocks));
+            assert_eq!(
+                (moved, next),
+                (x - 7, x - 7),
+                "HMCLR {} clocks after HMOVE",
+                clocks
+            );
+        }
+        let (x, moved, next) = hmclr_after(None);
+        assert_eq!((moved, next), (x - 7, x - 7));
+    }
+
+    #[test]
+    fn repeated_and_late_hmoves() {
+        const COLUP0: u8 = 0x44;
+
+        // Positions player 0, then strobes HMOVE at each of the given color clocks of a scanline.
+        // Returns how far the player moved on that scanline, and on the next one.
+        fn hmoves_at(hmp0: u8, clocks: &[usize]) -> (isize, isize) {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUP0, COLUP0);
+            tia.write(TiaWriteAddress::GRP0, 0xff);
+            tia.write(TiaWriteAddress::HMP0, hmp0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            clock_to_pixel(&mut tia, 80);
+            tia.write(TiaWriteAddress::RESP0, 0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 80);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            let x = first_pixel_of(&tia, COLUP0).unwrap() as isize;
+
+            let mut elapsed = 0;
+            for &clock in clocks {
+                clock_n(&mut tia, clock - elapsed);
+                tia.write(TiaWriteAddress::HMOVE, 0);
+                elapsed = clock;
+            }
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - elapsed);
+            let moved = first_pixel_of(&tia, COLUP0).unwrap() as isize;
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            let next = first_pixel_of(&tia, COLUP0).unwrap() as isize;
+            (moved - x, next - x)
+        }
+
+        // HMP0 asks for a single extra clock, and the comb pushes the player 8 pixels right
+        assert_eq!(hmoves_at(0x90, &[0]), (7, 7));
+
+        // A second HMOVE during HBLANK hands out another extra clock, with the same single comb
+        assert_eq!(hmoves_at(0x90, &[0, 20]), (6, 6));
+
+        // Its extra clocks don't move anything once the visible picture has started, and they
+        // aren't carried over to the next scanline either
+        assert_eq!(hmoves_at(0x90, &[0, H_BLANK_CLOCKS + 20]), (7, 7));
+        assert_eq!(hmoves_at(0x70, &[H_BLANK_CLOCKS + 20]), (0, 0));
+
+        // Strobed just before HBLANK ends, only the four extra clocks that come before the comb
+        // ends move the player
+        assert_eq!(hmoves_at(0x70, &[H_BLANK_CLOCKS - 12]), (4, 4));
+    }
+
+    #[test]
+    fn cxclr_after_hmove_sees_new_positions() {
+        const COLUP0: u8 = 0x44;
+        const COLUP1: u8 = 0x86;
+
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUP0, COLUP0);
+        tia.write(TiaWriteAddress::COLUP1, COLUP1);
+        tia.write(TiaWriteAddress::GRP0, 0x80);
+        tia.write(TiaWriteAddress::GRP1, 0x80);
+
+        // Put both players on the same pixel, so they collide
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        clock_to_pixel(&mut tia, 80);
+        tia.write(TiaWriteAddress::RESP0, 0);
+        tia.write(TiaWriteAddress::RESP1, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 80);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        let x = first_pixel_of(&tia, COLUP0).unwrap();
+        assert_eq!(tia.read(TiaReadAddress::CXPPMM) & 0x80, 0x80);
+
+        // Move player 1 eight pixels right, and clear the collision while the HMOVE is under way.
+        // Nothing is drawn during the comb, so the players don't collide there either.
+        tia.write(TiaWriteAddress::HMP1, 0x80);
+        tia.write(TiaWriteAddress::HMOVE, 0);
+        clock_n(&mut tia, 9);
+        tia.write(TiaWriteAddress::CXCLR, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - 9);
+        assert_eq!(first_pixel_of(&tia, COLUP1), Some(x + 8));
+        assert_eq!(tia.read(TiaReadAddress::CXPPMM), 0);
+
+        // Moving player 0 after it brings them back together
+        tia.write(TiaWriteAddress::HMCLR, 0);
+        tia.write(TiaWriteAddress::HMP0, 0x80);
+        tia.write(TiaWriteAddress::HMOVE, 0);
+        tia.write(TiaWriteAddress::CXCLR, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        assert_eq!(first_pixel_of(&tia, COLUP0), Some(x + 8));
+        assert_eq!(first_pixel_of(&tia, COLUP1), None);
+        assert_eq!(tia.read(TiaReadAddress::CXPPMM) & 0x80, 0x80);
+    }
+
+    #[test]
+    fn hmove_after_rhb_has_no_comb() {
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUBK, 0x0e);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+
+        clock_to_pixel(&mut tia, 2);
+        tia.write(TiaWriteAddress::HMOVE, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 2);
+
+        let black = color_rgba(DEFAULT_COLOR as u8);
+        assert!(!tia.get_scanline_pixels().contains(&black));
+    }
+
+    #[test]
+    fn vertical_delay_players() {
+        const COLUP0: u8 = 0x44;
+        const COLUP1: u8 = 0x86;
+
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUP0, COLUP0);
+        tia.write(TiaWriteAddress::COLUP1, COLUP1);
+        tia.write(TiaWriteAddress::VDELP0, 0x01);
+
+        // Player 0 at pixel 40, player 1 at pixel 80
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        clock_to_pixel(&mut tia, 35);
+        tia.write(TiaWriteAddress::RESP0, 0);
+        clock_n(&mut tia, 40);
+        tia.write(TiaWriteAddress::RESP1, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 75);
+
+        // Writes a register at the start of a scanline and returns how many pixels each player
+        // draws on it.
+        let mut line = |reg: TiaWriteAddress, val: u8| {
+            tia.write(reg, val);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            (count_pixels_of(&tia, COLUP0), count_pixels_of(&tia, COLUP1))
+        };
+
+        // With VDELP0 set, player 0 draws the value GRP0 had when GRP1 was last written
+        assert_eq!(line(TiaWriteAddress::GRP0, 0xff), (0, 0));
+        assert_eq!(line(TiaWriteAddress::GRP1, 0x0f), (8, 4));
+        assert_eq!(line(TiaWriteAddress::GRP0, 0x00), (8, 4));
+        assert_eq!(line(TiaWriteAddress::GRP1, 0x00), (0, 0));
+
+        // The same applies to player 1 with VDELP1, latched by writes to GRP0
+        line(TiaWriteAddress::VDELP0, 0x00);
+        line(TiaWriteAddress::GRP0, 0x00);
+        assert_eq!(line(TiaWriteAddress::VDELP1, 0x01), (0, 0));
+        assert_eq!(line(TiaWriteAddress::GRP1, 0xff), (0, 0));
+        assert_eq!(line(TiaWriteAddress::GRP0, 0x03), (2, 8));
+        assert_eq!(line(TiaWriteAddress::GRP1, 0x00), (2, 8));
+        assert_eq!(line(TiaWriteAddress::GRP0, 0x00), (0, 0));
+    }
+
+    #[test]
+    fn vertical_delay_ball() {
+        const COLUPF: u8 = 0x44;
+
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUPF, COLUPF);
+        tia.write(TiaWriteAddress::ENABL, 0x02);
+        tia.write(TiaWriteAddress::VDELBL, 0x01);
+
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        clock_to_pixel(&mut tia, 36);
+        tia.write(TiaWriteAddress::RESBL, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 36);
+
+        // Writes a register at the start of a scanline and returns whether the ball is drawn on it
+        let mut line = |reg: TiaWriteAddress, val: u8| {
+            tia.write(reg, val);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            first_pixel_of(&tia, COLUPF) == Some(40)
+        };
+
+        // ENABL is only copied to the delayed register by a GRP1 write
+        assert!(!line(TiaWriteAddress::VDELBL, 0x01));
+        assert!(line(TiaWriteAddress::GRP1, 0x00));
+        assert!(line(TiaWriteAddress::ENABL, 0x00));
+        assert!(!line(TiaWriteAddress::GRP1, 0x00));
+        assert!(!line(TiaWriteAddress::ENABL, 0x02));
+        assert!(line(TiaWriteAddress::GRP1, 0x00));
+
+        // Clearing VDELBL switches back to the live ENABL value
+        assert!(line(TiaWriteAddress::VDELBL, 0x00));
+        assert!(!line(TiaWriteAddress::ENABL, 0x00));
+    }
+
+    #[test]
+    fn playfield_is_aligned_on_every_scanline() {
+        const COLUPF: u8 = 0x44;
+
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUPF, COLUPF);
+        tia.write(TiaWriteAddress::PF0, 0xa0);
+        tia.write(TiaWriteAddress::PF1, 0x81);
+        tia.write(TiaWriteAddress::PF2, 0x3c);
+
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        let first = *tia.get_scanline_pixels();
+        assert_eq!(first_pixel_of(&tia, COLUPF), Some(4));
+
+        for _ in 0..3 {
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            assert_eq!(tia.get_scanline_pixels(), &first);
+        }
+
+        // The HMOVE comb hides the first 8 pixels but doesn't shift the rest of the pattern
+        tia.write(TiaWriteAddress::HMOVE, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        assert_eq!(tia.get_scanline_pixels()[8..], first[8..]);
+
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        assert_eq!(tia.get_scanline_pixels(), &first);
+    }
+
+    #[test]
+    fn snapshot_reports_registers() {
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUPF, 0x44);
+        tia.write(TiaWriteAddress::NUSIZ1, 0x25);
+        tia.write(TiaWriteAddress::PF0, 0x30);
+        tia.write(TiaWriteAddress::PF2, 0x80);
+        tia.write(TiaWriteAddress::GRP0, 0xff);
+
+        // Player 0 on top of the left-most playfield pixels
+        clock_n(&mut tia, H_BLANK_CLOCKS / 2);
+        tia.write(TiaWriteAddress::RESP0, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS / 2);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+
+        let snapshot = tia.snapshot();
+        assert_eq!(snapshot.colors.colupf, 0x44);
+        assert_eq!((snapshot.nusiz0, snapshot.nusiz1), (0x00, 0x25));
+
+        let mut playfield = [false; 20];
+        playfield[0] = true;
+        playfield[1] = true;
+        playfield[19] = true;
+        assert_eq!(snapshot.playfield, playfield);
+
+        assert_eq!(snapshot.p0_position, tia.p0.get_counter().value());
+        assert_eq!(snapshot.cxp0fb, 0x80);
+        assert_eq!(snapshot.cxp1fb, 0x00);
+
+        // Taking a snapshot doesn't disturb the TIA
+        assert_eq!(tia.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn playfield_center_seam() {
+        const COLUPF: u8 = 0x44;
+
+        // Draws a scanline with only the first bit of PF0 and the last bit of PF2 set, and returns
+        // the lit pixel ranges.
+        fn lit_pixels(ctrlpf: u8) -> Vec<usize> {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUPF, COLUPF);
+            tia.write(TiaWriteAddress::CTRLPF, ctrlpf);
+            tia.write(TiaWriteAddress::PF0, 0x10);
+            tia.write(TiaWriteAddress::PF2, 0x80);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+
+            let rgba = color_rgba(COLUPF);
+            (0..LINE_LENGTH)
+                .filter(|&x| tia.get_scanline_pixels()[x] == rgba)
+                .collect()
+        }
+
+        // The right half starts at pixel 80, repeating the left half...
+        let expected: Vec<usize> = [0..4, 76..80, 80..84, 156..160]
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(lit_pixels(0x00), expected);
+
+        // ...or mirroring it, so that the last PF2 bit is drawn on both sides of the seam
+        let expected: Vec<usize> = [0..4, 76..84, 156..160].into_iter().flatten().collect();
+        assert_eq!(lit_pixels(0x01), expected);
+    }
+
+    #[test]
+    fn score_mode_colors() {
+        const COLUP0: u8 = 0x44;
+        const COLUP1: u8 = 0x86;
+        const COLUPF: u8 = 0xc8;
+
+        fn draw(ctrlpf: u8) -> [Rgba<u8>; LINE_LENGTH] {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUP0, COLUP0);
+            tia.write(TiaWriteAddress::COLUP1, COLUP1);
+            tia.write(TiaWriteAddress::COLUPF, COLUPF);
+            tia.write(TiaWriteAddress::CTRLPF, ctrlpf);
+            tia.write(TiaWriteAddress::PF0, 0xf0);
+            tia.write(TiaWriteAddress::PF1, 0xff);
+            tia.write(TiaWriteAddress::PF2, 0xff);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            *tia.get_scanline_pixels()
+        }
+
+        let [p0, p1, pf] = [COLUP0, COLUP1, COLUPF].map(color_rgba);
+
+        // The left 20 playfield bits use COLUP0, the right 20 COLUP1
+        let pixels = draw(0x02);
+        assert!(pixels[..80].iter().all(|&p| p == p0));
+        assert!(pixels[80..].iter().all(|&p| p == p1));
+
+        // Playfield priority overrides score mode
+        let pixels = draw(0x06);
+        assert!(pixels.iter().all(|&p| p == pf));
+    }
+
+    #[test]
+    fn vdelp_toggle_takes_effect_immediately() {
+        const COLUP0: u8 = 0x44;
+
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUP0, COLUP0);
+
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+        clock_to_pixel(&mut tia, 35);
+        tia.write(TiaWriteAddress::RESP0, 0);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 35);
+
+        // Old value 0xff, current value 0x0f
+        tia.write(TiaWriteAddress::GRP0, 0xff);
+        tia.write(TiaWriteAddress::GRP1, 0x00);
+        tia.write(TiaWriteAddress::GRP0, 0x0f);
+
+        for (vdelp0, lit) in [(0x01, 8), (0x00, 4), (0x01, 8), (0x00, 4)] {
+            tia.write(TiaWriteAddress::VDELP0, vdelp0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+            assert_eq!(count_pixels_of(&tia, COLUP0), lit, "VDELP0 = {}", vdelp0);
+        }
+    }
+
+    #[test]
+    fn default_mode_players_over_playfield() {
+        const COLUP0: u8 = 0x44;
+        const COLUPF: u8 = 0xc8;
+
+        let mut tia = TIA::new();
+        tia.write(TiaWriteAddress::COLUP0, COLUP0);
+        tia.write(TiaWriteAddress::COLUPF, COLUPF);
+        tia.write(TiaWriteAddress::PF0, 0xf0);
+        tia.write(TiaWriteAddress::PF1, 0xff);
+        tia.write(TiaWriteAddress::PF2, 0xff);
+
+        clock_to_pixel(&mut tia, 35);
+        tia.write(TiaWriteAddress::RESP0, 0);
+        tia.write(TiaWriteAddress::GRP0, 0xff);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 35);
+        clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+
+        // The player is drawn on top of the playfield...
+        let x = first_pixel_of(&tia, COLUP0).expect("player not drawn");
+        let [p0, pf] = [COLUP0, COLUPF].map(color_rgba);
+        let pixels = tia.get_scanline_pixels();
+        assert!(pixels[x..x + 8].iter().all(|&p| p == p0));
+
+        // ...and the playfield shows COLUPF everywhere else
+        assert!(pixels[..x].iter().all(|&p| p == pf));
+        assert!(pixels[x + 8..].iter().all(|&p| p == pf));
+    }
+
+    #[test]
+    fn missile_copies_follow_player_copies() {
+        const COLUP0: u8 = 0x44;
+
+        // Draws a scanline with either player 0 or missile 0 reset at pixel 40, and returns the
+        // pixels each of the object's copies starts at.
+        fn copy_starts(nusiz: u8, object: TiaWriteAddress) -> Vec<usize> {
+            let mut tia = TIA::new();
+            tia.write(TiaWriteAddress::COLUP0, COLUP0);
+            tia.write(TiaWriteAddress::NUSIZ0, nusiz);
+            tia.write(TiaWriteAddress::GRP0, 0x80);
+            if matches!(object, TiaWriteAddress::RESM0) {
+                tia.write(TiaWriteAddress::GRP0, 0x00);
+                tia.write(TiaWriteAddress::ENAM0, 0x02);
+            }
+
+            clock_to_pixel(&mut tia, 40);
+            tia.write(object, 0);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE - H_BLANK_CLOCKS - 40);
+            clock_n(&mut tia, CLOCKS_PER_SCANLINE);
+
+            let lit = tia.get_scanline_pixels().map(|p| p == color_rgba(COLUP0));
+            (0..LINE_LENGTH)
+                .filter(|&x| lit[x] && (x == 0 || !lit[x - 1]))
+                .collect()
+        }
+
+        // Missiles are reset one pixel to the left of players
+        for (nusiz, copies) in [1, 2, 2, 3, 2, 1, 3, 1].into_iter().enumerate() {
+            let player = copy_starts(nusiz as u8, TiaWriteAddress::RESP0);
+            let missile = copy_starts(nusiz as u8, TiaWriteAddress::RESM0);
+
+            assert_eq!(missile.len(), copies, "NUSIZ0 = {}", nusiz);
+            let expected: Vec<usize> = player.iter().map(|x| x - 1).collect();
+            assert_eq!(missile, expected, "NUSIZ0 = {}", nusiz);
+        }
+    }
+}
@@ -1,20 +1,28 @@
 // http://www.qotile.net/minidig/docs/tia_color.html
 
+#[cfg(feature = "image")]
 use image::Rgba;
 
 pub const DEFAULT_COLOR: usize = 0;
 
+#[cfg(feature = "image")]
 lazy_static::lazy_static! {
-        pub static ref NTSC_PALETTE: Vec<Rgba<u8>> = ntsc_palette();
+        // Indexed by color code, i.e. the color register value shifted right by one
+        pub static ref NTSC_PALETTE: [Rgba<u8>; 128] = ntsc_palette();
 }
 
+#[cfg(feature = "image")]
 /// A color palette that maps 8-bit color codes (indexes) to RGBA pixels.
 pub(crate) type Palette = Vec<Rgba<u8>>;
 
+#[cfg(feature = "image")]
 /// Creates a palette of RGBA colors out of an `u32` array slice. Each number
 /// represents a 3-byte RGB color, where each channel is represented by 8 bits.
+///
+/// Note: TIA only uses 7 bits for representing colors, and bit 0 is unused. TIA
+/// palettes are indexed by color code, i.e. the color value shifted right by one.
 pub(crate) fn create_palette(colors: &[u32]) -> Palette {
-    let mut palette = Palette::with_capacity(colors.len() * 2);
+    let mut palette = Palette::with_capacity(colors.len());
     for color in colors {
         let color_rgba = Rgba([
             ((color >> 16) & 0xFF) as u8,
@@ -27,24 +35,11 @@ pub(crate) fn create_palette(colors: &[u32]) -> Palette {
     palette
 }
 
-/// Creates a TIA palette of RGBA colors out of an `u32` array slice. See
-/// [`common::colors::create_palette`] for the color representation details.
-///
-/// Note: TIA only uses 7 bits for representing colors, and bit 0 is unused. For
-/// simplicity, we just store each color twice so that accessing the palette
-/// with bit 0 set either to 0 or 1 yields the same RGBA pixel. See
-/// [`tia::VideoOutput.pixel`](../tia/struct.VideoOutput.html#structfield.pixel)
-pub(crate) fn create_tia_palette(colors: &[u32]) -> Palette {
-    create_palette(colors)
-        .iter()
-        .flat_map(|c| vec![*c, *c])
-        .collect()
-}
-
-/// Returns an NTSC palette. Source:
+#[cfg(feature = "image")]
+/// Returns an NTSC palette, one entry per color code. Source:
 /// http://www.qotile.net/minidig/docs/tia_color.html
-pub(crate) fn ntsc_palette() -> Palette {
-    create_tia_palette(&[
+pub(crate) fn ntsc_palette() -> [Rgba<u8>; 128] {
+    create_palette(&[
         0x000000, 0x404040, 0x6C6C6C, 0x909090, 0xB0B0B0, 0xC8C8C8, 0xDCDCDC, 0xECECEC, 0x444400,
         0x646410, 0x848424, 0xA0A034, 0xB8B840, 0xD0D050, 0xE8E85C, 0xFCFC68, 0x702800, 0x844414,
         0x985C28, 0xAC783C, 0xBC8C4C, 0xCCA05C, 0xDCB468, 0xECC878, 0x841800, 0x983418, 0xAC5030,
@@ -61,12 +56,15 @@ pub(crate) fn ntsc_palette() -> Palette {
         0xB4C078, 0xCCD488, 0xE0EC9C, 0x442800, 0x644818, 0x846830, 0xA08444, 0xB89C58, 0xD0B46C,
         0xE8CC7C, 0xFCE08C,
     ])
+    .try_into()
+    .expect("the NTSC palette has 128 colors")
 }
 
-/// Returns an NTSC palette. Source:
+#[cfg(feature = "image")]
+/// Returns an NTSC palette, one entry per color code. Source:
 /// https://www.randomterrain.com/atari-2600-memories-tutorial-andrew-davie-11.html
-pub fn _ntsc_palette_alternative() -> Palette {
-    create_tia_palette(&[
+pub fn _ntsc_palette_alternative() -> [Rgba<u8>; 128] {
+    create_palette(&[
         0x000000, 0x1A1A1A, 0x393939, 0x5B5B5B, 0x7E7E7E, 0xA2A2A2, 0xC7C7C7, 0xEDEDED, 0x190200,
         0x3A1F00, 0x5D4100, 0x826400, 0xA78800, 0xCCAD00, 0xF2D219, 0xFEFA40, 0x370000, 0x5E0800,
         0x832700, 0xA94900, 0xCF6C00, 0xF58F17, 0xFEB438, 0xFEDF6F, 0x470000, 0x730000, 0x981300,
@@ -83,9 +81,11 @@ pub fn _ntsc_palette_alternative() -> Palette {
         0xCEAD00, 0xF4D218, 0xFEFA40, 0x380000, 0x5F0800, 0x842700, 0xAA4900, 0xD06B00, 0xF68F18,
         0xFEB439, 0xFEDF70,
     ])
+    .try_into()
+    .expect("the NTSC palette has 128 colors")
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "image"))]
 mod tests {
     use super::*;
     use image::Pixel;
@@ -93,26 +93,25 @@ mod tests {
 
     #[test]
     fn creating_palette() {
-        assert_eq!(create_tia_palette(&[]), Palette::new());
-        assert_eq!(
-            create_tia_palette(&[0x123456]),
-            vec![
-                *Rgba::from_slice(&[0x12, 0x34, 0x56, 0xFF]),
-                *Rgba::from_slice(&[0x12, 0x34, 0x56, 0xFF]),
-            ]
-        );
+        assert_eq!(create_palette(&[]), Palette::new());
 
-        let three_color_palette = create_tia_palette(&[0xFEDCBA, 0x5A0345, 0x12A5E4]);
+        let three_color_palette = create_palette(&[0xFEDCBA, 0x5A0345, 0x12A5E4]);
         assert_eq!(
             three_color_palette,
             vec![
-                *Rgba::from_slice(&[0xFE, 0xDC, 0xBA, 0xFF]),
                 *Rgba::from_slice(&[0xFE, 0xDC, 0xBA, 0xFF]),
                 *Rgba::from_slice(&[0x5A, 0x03, 0x45, 0xFF]),
-                *Rgba::from_slice(&[0x5A, 0x03, 0x45, 0xFF]),
-                *Rgba::from_slice(&[0x12, 0xA5, 0xE4, 0xFF]),
                 *Rgba::from_slice(&[0x12, 0xA5, 0xE4, 0xFF]),
             ]
         );
     }
+
+    #[test]
+    fn ntsc_palette_is_indexed_by_color_code() {
+        assert_eq!(NTSC_PALETTE.len(), 128);
+        assert_eq!(NTSC_PALETTE[0], Rgba([0x00, 0x00, 0x00, 0xFF]));
+        // COLUBK = $0E, the brightest grey
+        assert_eq!(NTSC_PALETTE[0x0E >> 1], Rgba([0xEC, 0xEC, 0xEC, 0xFF]));
+        assert_eq!(NTSC_PALETTE[127], Rgba([0xFC, 0xE0, 0x8C, 0xFF]));
+    }
 }
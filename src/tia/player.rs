@@ -1,9 +1,8 @@
 use super::graphic::Graphic;
-use super::SharedColor;
-use crate::tia::{counter::Counter, graphic::ScanCounter, PlayerType};
+use crate::tia::{color::Colors, counter::Counter, graphic::ScanCounter, PlayerType};
 
+#[derive(Clone)]
 pub(crate) struct Player {
-    colors: SharedColor,
     hmove_offset: u8,
     ctr: Counter,
     scan_counter: ScanCounter,
@@ -21,9 +20,8 @@ pub(crate) struct Player {
 }
 
 impl Player {
-    pub fn new(colors: SharedColor, player: PlayerType) -> Self {
+    pub fn new(player: PlayerType) -> Self {
         Self {
-            colors,
             player,
 
             hmove_offset: 0,
@@ -88,7 +86,7 @@ impl Graphic for Player {
     }
 
     fn pixel_bit(&self) -> bool {
-        self.scan_counter.bit_idx.map_or(false, |x| {
+        self.scan_counter.bit_idx.is_some_and(|x| {
             (0..8).contains(&x) && {
                 let graphic = if self.vdel {
                     self.old_value
@@ -110,12 +108,13 @@ impl Graphic for Player {
             || (count == 15 && (nusiz == 0b100 || nusiz == 0b110))
     }
 
-    fn get_color(&self) -> Option<u8> {
+    #[inline]
+    fn get_color(&self, colors: &Colors) -> Option<u8> {
         self.scan_counter
             .bit_value
             .and_then(|bit_value| match (bit_value, &self.player) {
-                (true, PlayerType::Player0) => Some(self.colors.borrow().colup0()),
-                (true, PlayerType::Player1) => Some(self.colors.borrow().colup1()),
+                (true, PlayerType::Player0) => Some(colors.colup0()),
+                (true, PlayerType::Player1) => Some(colors.colup1()),
                 (false, _) => None,
             })
     }
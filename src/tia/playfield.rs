@@ -1,10 +1,9 @@
-use super::SharedColor;
-use crate::tia::counter::Counter;
+use crate::tia::{color::Colors, counter::Counter};
 
 #[allow(dead_code)]
 mod pf_data {
+    use core::array;
     use modular_bitfield::prelude::*;
-    use std::array;
     // 20-bit playfield
     // .... | .... .... | .... ....
     // PF0  |    PF1    |    PF2
@@ -33,8 +32,8 @@ enum PlayfieldSide {
     Right,
 }
 
+#[derive(Clone)]
 pub(crate) struct Playfield {
-    colors: SharedColor,
     ctr: Counter,
 
     pf_data: PlayfieldData,
@@ -42,13 +41,15 @@ pub(crate) struct Playfield {
     score_mode: bool,
     priority: bool,
 
-    graphic_bit_value: Option<u8>,
+    // Whether the playfield bit for the last pixel drawn was set
+    graphic_bit_value: bool,
+    // Whether the last pixel drawn was on the left half of the screen
+    left_side: bool,
 }
 
 impl Playfield {
-    pub fn new(colors: SharedColor) -> Self {
+    pub fn new() -> Self {
         Self {
-            colors,
             ctr: Counter::default(),
 
             pf_data: PlayfieldData::from_bytes([0, 0, 0]),
@@ -57,7 +58,8 @@ impl Playfield {
             score_mode: false,
             priority: false,
 
-            graphic_bit_value: None,
+            graphic_bit_value: false,
+            left_side: true,
         }
     }
 
@@ -88,9 +90,10 @@ impl Playfield {
         let ctr = self.ctr.value() as usize;
         let pf_x = ctr % 20;
         let data_bits = self.pf_data.bits();
-        let colors = self.colors.borrow();
 
-        // The playfield makes up the left-most side of the screen.
+        // The playfield makes up the left-most side of the screen. Counter value 20 is pixel 80:
+        // the CNT signal is decoded at HSYNC count 36 (pixel 76) and takes effect one playfield
+        // bit (4 pixels) later.
         let side = if ctr < 20 {
             PlayfieldSide::Left
         } else {
@@ -105,12 +108,15 @@ impl Playfield {
             _ => pf_x,
         };
 
-        self.graphic_bit_value = match (&side, data_bits[idx], self.score_mode) {
-            (PlayfieldSide::Left, true, true) => Some(colors.colup0()),
-            (PlayfieldSide::Right, true, true) => Some(colors.colup1()),
-            (_, true, _) => Some(colors.colupf()),
-            _ => None,
-        };
+        self.left_side = matches!(side, PlayfieldSide::Left);
+        self.graphic_bit_value = data_bits[idx];
+    }
+
+    // Realigns the playfield with the left edge of the screen. The counter already wraps after the
+    // 160 visible clocks of a scanline, this keeps anything that shifts it (such as a write to
+    // RSYNC) from carrying over into the following scanlines.
+    pub fn start_scanline(&mut self) {
+        self.ctr.reset_to(0);
     }
 
     pub fn clock(&mut self) {
@@ -118,12 +124,33 @@ impl Playfield {
         self.ctr.clock();
     }
 
+    pub fn bits(&self) -> [bool; PF_LENGTH] {
+        self.pf_data.bits()
+    }
+
     pub fn priority(&self) -> bool {
         self.priority
     }
 
-    pub fn get_color(&self) -> Option<u8> {
-        self.graphic_bit_value
+    pub fn score_mode(&self) -> bool {
+        self.score_mode
+    }
+
+    pub fn left_side(&self) -> bool {
+        self.left_side
+    }
+
+    #[inline]
+    pub fn get_color(&self, colors: &Colors) -> Option<u8> {
+        if !self.graphic_bit_value {
+            return None;
+        }
+
+        match (self.left_side, self.score_mode) {
+            (true, true) => Some(colors.colup0()),
+            (false, true) => Some(colors.colup1()),
+            (_, false) => Some(colors.colupf()),
+        }
     }
 }
 
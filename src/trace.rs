@@ -0,0 +1,117 @@
+use crate::opcode::{AddressingMode, Instruction, Opcode, OPCODES};
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+
+/// One instruction run by `EmulatorCore::run_instructions`, with the state the console was in just
+/// before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Address of the instruction.
+    pub pc: u16,
+    /// The opcode and its operand, 1 to 3 bytes.
+    pub bytes: Vec<u8>,
+    /// The instruction in assembler syntax, e.g. `STA $02` or `BNE $F00A`.
+    pub disassembly: String,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    /// CPU cycles run since the last reset, see `EmulatorCore::total_cycles`.
+    pub cycles: u64,
+    /// Where the beam was when the instruction's first cycle started, in the terms of
+    /// `EmulatorCore::beam_position`.
+    pub scanline: usize,
+    pub color_clock: usize,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(
+            f,
+            "{:04X}  {:<8}  {:<13} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{} SL:{} CLK:{}",
+            self.pc,
+            bytes.join(" "),
+            self.disassembly,
+            self.a,
+            self.x,
+            self.y,
+            self.p,
+            self.sp,
+            self.cycles,
+            self.scanline,
+            self.color_clock
+        )
+    }
+}
+
+// The number of bytes the instruction starting with `opcode` takes up. Opcodes the CPU doesn't
+// support are shown as a single byte.
+pub(crate) fn instruction_len(opcode: u8) -> usize {
+    match OPCODES[opcode as usize] {
+        Opcode(Instruction::None, ..) => 1,
+        Opcode(_, addr_mode, ..) => addr_mode.n_bytes(),
+    }
+}
+
+// Disassembles the instruction in `bytes`, which starts at `pc`. Branch targets are resolved to
+// addresses.
+pub(crate) fn disassemble(pc: u16, bytes: &[u8]) -> String {
+    let Opcode(instruction, addr_mode, ..) = OPCODES[bytes[0] as usize];
+    if let Instruction::None = instruction {
+        return format!(".byte ${:02X}", bytes[0]);
+    }
+
+    let mnemonic = String::from(instruction.mnemonic());
+    let byte = || bytes[1];
+    let word = || u16::from_le_bytes([bytes[1], bytes[2]]);
+    let operand = match addr_mode {
+        AddressingMode::Implied => return mnemonic,
+        AddressingMode::Accumulator => String::from("A"),
+        AddressingMode::Immediate => format!("#${:02X}", byte()),
+        AddressingMode::ZeroPageIndexed => format!("${:02X}", byte()),
+        AddressingMode::ZeroPageX => format!("${:02X},X", byte()),
+        AddressingMode::ZeroPageY => format!("${:02X},Y", byte()),
+        AddressingMode::Absolute => format!("${:04X}", word()),
+        AddressingMode::AbsoluteX => format!("${:04X},X", word()),
+        AddressingMode::AbsoluteY => format!("${:04X},Y", word()),
+        AddressingMode::Indirect => format!("(${:04X})", word()),
+        AddressingMode::IndexedIndirect => format!("(${:02X},X)", byte()),
+        AddressingMode::IndirectIndexed => format!("(${:02X}),Y", byte()),
+        AddressingMode::Relative => {
+            let target = pc.wrapping_add(2).wrapping_add(byte() as i8 as u16);
+            format!("${:04X}", target)
+        }
+        AddressingMode::None => unreachable!(),
+    };
+    format!("{} {}", mnemonic, operand)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembles_each_addressing_mode() {
+        let cases: [(&[u8], &str); 9] = [
+            (&[0xea], "NOP"),
+            (&[0x0a], "ASL A"),
+            (&[0xa9, 0x10], "LDA #$10"),
+            (&[0x85, 0x02], "STA $02"),
+            (&[0xb6, 0x80], "LDX $80,Y"),
+            (&[0x9d, 0x00, 0x10], "STA $1000,X"),
+            (&[0x6c, 0xfc, 0xff], "JMP ($FFFC)"),
+            (&[0xb1, 0x80], "LDA ($80),Y"),
+            (&[0x02], "JAM"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(instruction_len(bytes[0]), bytes.len());
+            assert_eq!(disassemble(0xf000, bytes), expected);
+        }
+
+        // Branches show where they go, backwards too
+        assert_eq!(disassemble(0xf010, &[0xd0, 0x04]), "BNE $F016");
+        assert_eq!(disassemble(0xf010, &[0xd0, 0xfc]), "BNE $F00E");
+    }
+}
@@ -0,0 +1,98 @@
+//! Runs the ROMs listed in `goldens.txt` and compares the hash of the last frame against the
+//! expected value. This catches changes to the rendered output that tests of individual TIA
+//! objects miss.
+
+use atari2600_lib::{init_emulator_from_bytes, EmulatorCore};
+use std::{env, fmt::Write, fs};
+
+const GOLDENS: &str = "tests/goldens.txt";
+
+struct Golden<'a> {
+    rom: &'a str,
+    frames: usize,
+    hash: u64,
+}
+
+fn parse_golden(line: &str) -> Golden<'_> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields[..] {
+        [rom, frames, hash] => Golden {
+            rom,
+            frames: frames.parse().expect("invalid frame count"),
+            hash: u64::from_str_radix(hash.trim_start_matches("0x"), 16).expect("invalid hash"),
+        },
+        _ => panic!("invalid golden: '{}'", line),
+    }
+}
+
+fn run_rom(rom: Vec<u8>, frames: usize) -> EmulatorCore {
+    let mut emulator = init_emulator_from_bytes(rom).expect("ROM should load");
+    for _ in 0..frames {
+        emulator.run();
+    }
+    emulator
+}
+
+// Writes the frame next to the build output as a PPM file, to look at when a hash doesn't match
+#[cfg(feature = "image")]
+fn dump_frame(emulator: &EmulatorCore, golden: &Golden) -> String {
+    use std::path::Path;
+
+    let name = Path::new(golden.rom).file_stem().unwrap().to_string_lossy();
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(format!("golden-{}-{}.ppm", name, golden.frames));
+    let mut ppm = b"P6\n160 192\n255\n".to_vec();
+    ppm.extend(emulator.frame_rgb24());
+    fs::write(&path, ppm).expect("frame should be written");
+
+    format!(", frame written to {}", path.display())
+}
+
+#[cfg(not(feature = "image"))]
+fn dump_frame(_emulator: &EmulatorCore, _golden: &Golden) -> String {
+    String::new()
+}
+
+#[test]
+fn frame_hashes_match_goldens() {
+    let goldens = fs::read_to_string(GOLDENS).expect("goldens should be readable");
+    let update = env::var_os("UPDATE_GOLDENS").is_some();
+
+    let mut updated = String::new();
+    let mut mismatches = Vec::new();
+    for line in goldens.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            updated.push_str(line);
+            updated.push('\n');
+            continue;
+        }
+
+        let golden = parse_golden(line);
+        let Ok(rom) = fs::read(golden.rom) else {
+            eprintln!("skipping {}: ROM not found", golden.rom);
+            updated.push_str(line);
+            updated.push('\n');
+            continue;
+        };
+
+        let emulator = run_rom(rom, golden.frames);
+        let hash = emulator.frame_hash();
+        writeln!(updated, "{} {} {:016x}", golden.rom, golden.frames, hash).unwrap();
+
+        if hash != golden.hash && !update {
+            mismatches.push(format!(
+                "{} after {} frames: expected {:016x}, got {:016x}{}",
+                golden.rom,
+                golden.frames,
+                golden.hash,
+                hash,
+                dump_frame(&emulator, &golden)
+            ));
+        }
+    }
+
+    if update {
+        fs::write(GOLDENS, updated).expect("goldens should be writable");
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}